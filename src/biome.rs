@@ -1,54 +1,413 @@
-use std::collections::VecDeque;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    fs,
+    path::Path,
+};
 
 use bevy::prelude::*;
-use bevy_prng::WyRand;
-use bevy_rand::prelude::Entropy;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    map::{Map, Sector},
-    settings::MapGenSettings,
+    map_image::MapImages, resource::ResourceNodeType, settings::MapGenSettings, water::WaterBody,
+    worldgen::WorldSector,
 };
 
-#[derive(Debug, Clone, Copy)]
-pub enum Biome {
-    Plains,
-    Forest,
-    Mountains,
-    Desert,
-    Water,
+/// A biome, as an index into the [`BiomeRegistry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Biome(pub u16);
+
+/// Every biome the map can use, loaded from `assets/biomes.ron`.
+/// In [`BiomeMode::Climate`] each land sector gets the first biome in the list whose climate it fits.
+#[derive(Asset, Resource, TypePath, Debug, Clone, Deserialize)]
+pub struct BiomeRegistry {
+    pub biomes: Vec<BiomeDef>,
+
+    /// The land biomes [`BiomeMode::FloodFill`] draws its seeds from, by name.
+    /// This is kept apart from the order of `biomes` so reordering them for
+    /// [`BiomeMode::Climate`] doesn't change flood filled maps. Every land biome is used if empty.
+    #[serde(default)]
+    pub flood_fill_order: Vec<String>,
+}
+
+/// The definition of a single biome.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BiomeDef {
+    pub name: String,
+
+    /// The color of this biome on the map, as sRGB.
+    pub color: (u8, u8, u8),
+
+    /// The color of lakes and inland seas of this biome, if it's water and they should
+    /// stand out from the ocean.
+    #[serde(default)]
+    pub lake_color: Option<(u8, u8, u8)>,
+
+    /// The cost per unit of traversing a sector of this biome (for road pathfinding).
+    pub cost: f32,
+
+    /// Whether this biome is water. Sectors below the water cutoff get the first water biome.
+    #[serde(default)]
+    pub water: bool,
+
+    /// Whether cities may be founded in this biome.
+    #[serde(default)]
+    pub cities: bool,
+
+    /// The kinds of resource node that can spawn in this biome.
+    #[serde(default)]
+    pub nodes: Vec<ResourceNodeType>,
+
+    /// Where this biome appears in [`BiomeMode::Climate`], or `None` if it never does.
+    #[serde(default)]
+    pub climate: Option<ClimateLimits>,
+}
+
+/// The range of conditions a biome appears in. Limits are exclusive, and those left out don't apply.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ClimateLimits {
+    pub min_height: Option<f32>,
+    pub max_height: Option<f32>,
+
+    /// Temperature from 0 (cold) to 1 (hot).
+    pub min_temperature: Option<f32>,
+    pub max_temperature: Option<f32>,
+
+    /// Moisture from 0 (dry) to 1 (wet).
+    pub min_moisture: Option<f32>,
+    pub max_moisture: Option<f32>,
+
+    /// Whether the moisture limits are scaled by `0.5 + temperature`, as hotter sectors
+    /// lose more water and need more rain to pass them.
+    pub evaporation: bool,
+}
+
+impl BiomeDef {
+    /// The color of a sector of this biome on the map, as sRGB.
+    pub fn map_color(&self, water_body: Option<WaterBody>) -> (u8, u8, u8) {
+        match water_body {
+            Some(WaterBody::Lake | WaterBody::InlandSea) => self.lake_color.unwrap_or(self.color),
+            _ => self.color,
+        }
+    }
+}
+
+impl ClimateLimits {
+    fn contains(&self, height: f32, temperature: f32, moisture: f32) -> bool {
+        let within = |value: f32, min: Option<f32>, max: Option<f32>| {
+            min.is_none_or(|min| value > min) && max.is_none_or(|max| value < max)
+        };
+        let scale = if self.evaporation {
+            0.5 + temperature
+        } else {
+            1.0
+        };
+        within(height, self.min_height, self.max_height)
+            && within(temperature, self.min_temperature, self.max_temperature)
+            && within(
+                moisture,
+                self.min_moisture.map(|min| min * scale),
+                self.max_moisture.map(|max| max * scale),
+            )
+    }
+}
+
+impl BiomeRegistry {
+    /// Read a registry straight from a RON file, for use outside of the asset system.
+    pub fn load(path: &Path) -> Result<BiomeRegistry, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let registry: BiomeRegistry = ron::from_str(&text).map_err(|err| err.to_string())?;
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    /// Check the registry has everything map generation needs.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.biomes.len() > u16::MAX as usize {
+            return Err(format!("there can't be more than {} biomes", u16::MAX));
+        }
+        if !self.biomes.iter().any(|def| def.water) {
+            return Err("there must be at least one water biome".to_string());
+        }
+        if !self.biomes.iter().any(|def| !def.water) {
+            return Err("there must be at least one land biome".to_string());
+        }
+        for name in &self.flood_fill_order {
+            match self.by_name(name) {
+                Some(biome) if self.get(biome).water => {
+                    return Err(format!("flood fill biome {} is water", name));
+                }
+                Some(_) => {}
+                None => return Err(format!("flood fill biome {} doesn't exist", name)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, biome: Biome) -> &BiomeDef {
+        &self.biomes[biome.0 as usize]
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<Biome> {
+        self.biomes
+            .iter()
+            .position(|def| def.name == name)
+            .map(|i| Biome(i as u16))
+    }
+
+    /// The biome given to sectors below the water cutoff.
+    pub fn water(&self) -> Biome {
+        self.iter()
+            .find(|(_, def)| def.water)
+            .map(|(biome, _)| biome)
+            .unwrap()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Biome, &BiomeDef)> {
+        self.biomes
+            .iter()
+            .enumerate()
+            .map(|(i, def)| (Biome(i as u16), def))
+    }
+
+    /// Every biome that isn't water.
+    pub fn land(&self) -> Vec<Biome> {
+        self.iter()
+            .filter(|(_, def)| !def.water)
+            .map(|(biome, _)| biome)
+            .collect()
+    }
+
+    /// The land biomes flood fill seeds are drawn from, in the order of `flood_fill_order`.
+    pub fn flood_fill_biomes(&self) -> Vec<Biome> {
+        if self.flood_fill_order.is_empty() {
+            return self.land();
+        }
+        self.flood_fill_order
+            .iter()
+            .map(|name| self.by_name(name).unwrap())
+            .collect()
+    }
+
+    /// The first land biome whose climate limits contain these conditions.
+    /// Falls back to the first land biome if none do.
+    pub fn classify(&self, height: f32, temperature: f32, moisture: f32) -> Biome {
+        self.iter()
+            .filter(|(_, def)| !def.water)
+            .find(|(_, def)| {
+                def.climate
+                    .as_ref()
+                    .is_some_and(|climate| climate.contains(height, temperature, moisture))
+            })
+            .map(|(biome, _)| biome)
+            .unwrap_or_else(|| self.land()[0])
+    }
+
+    /// The biome whose map color, or lake color, is closest to an sRGB color.
+    pub fn closest_color(&self, color: (u8, u8, u8)) -> Biome {
+        let distance = |other: (u8, u8, u8)| {
+            let dr = color.0 as i32 - other.0 as i32;
+            let dg = color.1 as i32 - other.1 as i32;
+            let db = color.2 as i32 - other.2 as i32;
+            dr * dr + dg * dg + db * db
+        };
+        self.iter()
+            .min_by_key(|(_, def)| {
+                def.lake_color
+                    .map_or(i32::MAX, distance)
+                    .min(distance(def.color))
+            })
+            .map(|(biome, _)| biome)
+            .unwrap()
+    }
+
+    pub fn is_water(&self, biome: Option<Biome>) -> bool {
+        biome.is_some_and(|biome| self.get(biome).water)
+    }
 }
 
+#[derive(Resource)]
+pub struct BiomeListHandle(pub Handle<BiomeRegistry>);
+
+pub fn load_biome_list(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(BiomeListHandle(asset_server.load("biomes.ron")));
+}
+
+/// Make the loaded biome list available as a [`BiomeRegistry`] resource.
+pub fn insert_biome_registry(
+    mut commands: Commands,
+    handle: Res<BiomeListHandle>,
+    registries: Res<Assets<BiomeRegistry>>,
+) {
+    let registry = registries.get(&handle.0).unwrap();
+    if let Err(err) = registry.validate() {
+        panic!("Invalid biome list: {}", err);
+    }
+    commands.insert_resource(registry.clone());
+}
+
+/// How biomes are assigned to sectors during map generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BiomeMode {
+    /// Classify each sector from its height, temperature and moisture.
+    #[default]
+    Climate,
+
+    /// Spread random biome seeds breadth-first, ignoring height except for water.
+    FloodFill,
+}
+
+/// Assign a biome and traversal cost to every sector.
+/// A biome map, if there is one, takes the place of the [`BiomeMode`].
 pub fn generate_biomes(
-    mut map_query: Query<(&Map, &mut Entropy<WyRand>)>,
-    mut sector_query: Query<&mut Sector>,
-    settings: Res<MapGenSettings>,
+    sectors: &mut [WorldSector],
+    settings: &MapGenSettings,
+    biomes: &BiomeRegistry,
+    images: &MapImages,
+    rng: &mut impl Rng,
 ) {
-    let (map, mut rng) = map_query.single_mut().unwrap();
+    if images.biome_map.is_some() {
+        for sector in sectors.iter_mut() {
+            let biome = images.biome_at(sector.site, settings, biomes).unwrap();
+            sector.cost = biomes.get(biome).cost;
+            sector.biome = Some(biome);
+        }
+        return;
+    }
 
+    match settings.biome_mode {
+        BiomeMode::Climate => classify_biomes(sectors, settings, biomes),
+        BiomeMode::FloodFill => flood_fill_biomes(sectors, settings, biomes, rng),
+    }
+}
+
+/// Assign biomes Whittaker-style from height, temperature and moisture.
+fn classify_biomes(sectors: &mut [WorldSector], settings: &MapGenSettings, biomes: &BiomeRegistry) {
+    let temperatures = temperature_field(sectors, settings);
+    let moistures = moisture_field(sectors, settings);
+
+    for (i, sector) in sectors.iter_mut().enumerate() {
+        let biome = if sector.height < settings.water_cutoff {
+            biomes.water()
+        } else {
+            biomes.classify(sector.height, temperatures[i], moistures[i])
+        };
+
+        sector.cost = biomes.get(biome).cost;
+        sector.biome = Some(biome);
+    }
+}
+
+/// Temperature of every sector from 0 (cold) to 1 (hot).
+/// It falls off from the south edge of the map to the north edge and with altitude above the water.
+fn temperature_field(sectors: &[WorldSector], settings: &MapGenSettings) -> Vec<f32> {
+    sectors
+        .iter()
+        .map(|sector| {
+            let latitude = (sector.site.y / settings.size.y).clamp(0.0, 1.0);
+            let base = settings.south_temperature
+                + (settings.north_temperature - settings.south_temperature) * latitude;
+            let altitude = (sector.height - settings.water_cutoff).max(0.0);
+            (base - altitude * settings.temperature_lapse).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+/// Moisture of every sector from 0 (dry) to 1 (wet).
+/// This averages how close the sector is to water with how much rain the prevailing wind
+/// still carries when it arrives, so the far side of high ground is left in a rain shadow.
+fn moisture_field(sectors: &[WorldSector], settings: &MapGenSettings) -> Vec<f32> {
+    let is_water = |sector: &WorldSector| sector.height < settings.water_cutoff;
+
+    // Distance to the nearest water along the sector graph, using Dijkstra from every water sector.
+    // Distances are never negative, so their bit patterns sort in the same order as the floats.
+    let mut distances = vec![f32::INFINITY; sectors.len()];
+    let mut queue = BinaryHeap::new();
+    for (i, sector) in sectors.iter().enumerate() {
+        if is_water(sector) {
+            distances[i] = 0.0;
+            queue.push(Reverse((0.0f32.to_bits(), i)));
+        }
+    }
+    while let Some(Reverse((distance, i))) = queue.pop() {
+        let distance = f32::from_bits(distance);
+        if distance > distances[i] {
+            continue;
+        }
+        for neighbor in &sectors[i].neighbors {
+            let next = distance + sectors[i].centroid.distance(sectors[*neighbor].centroid);
+            if next < distances[*neighbor] {
+                distances[*neighbor] = next;
+                queue.push(Reverse((next.to_bits(), *neighbor)));
+            }
+        }
+    }
+
+    // Carry rain downwind, sweeping sectors in the order the wind reaches them.
+    // Every rise in height along the way wrings some of the rain out.
+    let wind = settings.wind_direction.normalize_or_zero();
+    let mut order: Vec<usize> = (0..sectors.len()).collect();
+    order.sort_by(|a, b| {
+        sectors[*a]
+            .site
+            .dot(wind)
+            .total_cmp(&sectors[*b].site.dot(wind))
+    });
+    let mut rain = vec![1.0f32; sectors.len()];
+    for i in order {
+        if is_water(&sectors[i]) {
+            continue;
+        }
+        let upwind: Vec<f32> = sectors[i]
+            .neighbors
+            .iter()
+            .filter(|neighbor| sectors[**neighbor].site.dot(wind) < sectors[i].site.dot(wind))
+            .map(|neighbor| {
+                let rise = (sectors[i].height - sectors[*neighbor].height).max(0.0);
+                rain[*neighbor] * (1.0 - rise * settings.rain_shadow).max(0.0)
+            })
+            .collect();
+        if !upwind.is_empty() {
+            rain[i] = upwind.iter().sum::<f32>() / upwind.len() as f32;
+        }
+    }
+
+    distances
+        .iter()
+        .zip(&rain)
+        .map(|(distance, rain)| {
+            let near_water = (-distance / settings.moisture_falloff).exp();
+            (near_water + rain) / 2.0
+        })
+        .collect()
+}
+
+/// Assign biomes by spreading random seeds breadth-first.
+fn flood_fill_biomes(
+    sectors: &mut [WorldSector],
+    settings: &MapGenSettings,
+    biomes: &BiomeRegistry,
+    rng: &mut impl Rng,
+) {
+    let land = biomes.flood_fill_biomes();
     let mut seeds: VecDeque<BiomeSeed> = VecDeque::new();
 
     // Generate random biome seeds
     for _ in 0..1000 {
-        let rand_index = rng.random_range(0..map.sectors.len());
-        let rand_sector = map.sectors[rand_index];
+        let rand_sector = rng.random_range(0..sectors.len());
 
         // Check this isn't already a seed.
-        if seeds.iter().any(|seed| seed.e_sector == rand_sector) {
+        if seeds.iter().any(|seed| seed.sector == rand_sector) {
             continue;
         }
 
-        let biome_type: Biome = match rng.random_range(0..4) {
-            0 => Biome::Plains,
-            1 => Biome::Forest,
-            2 => Biome::Mountains,
-            3 => Biome::Desert,
-            _ => unreachable!(),
-        };
+        let biome_type = land[rng.random_range(0..land.len())];
 
         seeds.push_back(BiomeSeed {
             biome: biome_type,
-            e_sector: rand_sector,
+            sector: rand_sector,
         });
 
         if seeds.len() >= settings.biome_seed_num as usize {
@@ -57,8 +416,8 @@ pub fn generate_biomes(
     }
 
     // Spread biome seeds breadth-first until there are no more
-    while let Some(BiomeSeed { biome, e_sector }) = seeds.pop_front() {
-        let mut sector = sector_query.get_mut(e_sector).unwrap();
+    while let Some(BiomeSeed { biome, sector }) = seeds.pop_front() {
+        let sector = &mut sectors[sector];
 
         // Skip sectors that already have a biome
         if sector.biome.is_some() {
@@ -66,24 +425,18 @@ pub fn generate_biomes(
         }
 
         let real_biome = if sector.height < settings.water_cutoff {
-            Biome::Water
+            biomes.water()
         } else {
             biome
         };
 
-        sector.cost = match real_biome {
-            Biome::Plains => 1.0,
-            Biome::Forest => 5.0,
-            Biome::Mountains => 10.0,
-            Biome::Desert => 1.0,
-            Biome::Water => 100.0,
-        };
+        sector.cost = biomes.get(real_biome).cost;
         sector.biome = Some(real_biome);
 
         for neighbor in &sector.neighbors {
             seeds.push_back(BiomeSeed {
                 biome,
-                e_sector: *neighbor,
+                sector: *neighbor,
             });
         }
     }
@@ -92,5 +445,5 @@ pub fn generate_biomes(
 /// Helper struct used during biome generation.
 struct BiomeSeed {
     biome: Biome,
-    e_sector: Entity,
+    sector: usize,
 }
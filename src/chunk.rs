@@ -0,0 +1,184 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        primitives::Aabb,
+    },
+};
+
+use crate::{
+    biome::BiomeRegistry,
+    map::{Map, Sector},
+    overlay::OverlayColors,
+    settings::{DisplaySettings, MapGenSettings},
+    shading::MapPalette,
+};
+
+/// A square block of the map whose sectors are drawn together as one mesh.
+/// The mesh is only rebuilt when one of its sectors changes, and only once the chunk is in view,
+/// so large maps don't have to be redrawn whole.
+#[derive(Component, Debug)]
+pub struct MapChunk {
+    pub sectors: Vec<Entity>,
+
+    /// Whether the mesh is out of date and needs rebuilding next time the chunk is in view.
+    pub stale: bool,
+}
+
+/// The [`MapChunk`] a sector is drawn in.
+#[derive(Component, Debug)]
+pub struct InChunk(pub Entity);
+
+/// Split the map into chunks of `map_chunk_size` by sector centroid.
+/// Their meshes are built by [`rebuild_map_chunks`] as they come into view.
+pub fn add_map_chunks(
+    mut commands: Commands,
+    meshes: Res<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    map_query: Query<(Entity, &Map)>,
+    sector_query: Query<&Sector>,
+    display_settings: Res<DisplaySettings>,
+    map_gen_settings: Res<MapGenSettings>,
+) {
+    let (e_map, map) = map_query.single().unwrap();
+
+    let mut cells: HashMap<IVec2, Vec<Entity>> = HashMap::new();
+    for e_sector in &map.sectors {
+        let sector = sector_query.get(*e_sector).unwrap();
+        let cell = (sector.centroid / display_settings.map_chunk_size)
+            .floor()
+            .as_ivec2();
+        cells.entry(cell).or_default().push(*e_sector);
+    }
+    let mut cells: Vec<(IVec2, Vec<Entity>)> = cells.into_iter().collect();
+    cells.sort_by_key(|(cell, _)| (cell.y, cell.x));
+
+    // Every chunk shares one material, as colors come from the mesh
+    let material = materials.add(Color::WHITE);
+    for (_, sectors) in cells {
+        // Sectors don't move, so the bounds can be set now for culling to use before the
+        // mesh exists
+        let (min, max) = sectors
+            .iter()
+            .flat_map(|e_sector| sector_query.get(*e_sector).unwrap().border.iter())
+            .fold((Vec2::MAX, Vec2::MIN), |(min, max), point| {
+                (min.min(*point), max.max(*point))
+            });
+
+        let e_chunk = commands
+            .spawn((
+                Mesh2d(meshes.reserve_handle()),
+                MeshMaterial2d(material.clone()),
+                Aabb::from_min_max(min.extend(0.0), max.extend(0.0)),
+                Transform::from_xyz(0.0, 0.0, 0.0),
+            ))
+            .id();
+        for e_sector in &sectors {
+            commands.entity(*e_sector).insert(InChunk(e_chunk));
+        }
+        commands.entity(e_chunk).insert(MapChunk {
+            sectors,
+            stale: true,
+        });
+        commands.entity(e_map).add_child(e_chunk);
+    }
+
+    commands.insert_resource(MapPalette::new(
+        &sector_query,
+        &display_settings,
+        &map_gen_settings,
+    ));
+}
+
+/// Mark the chunks of changed sectors as stale, along with their neighbors' chunks,
+/// whose colors can be blended with theirs.
+pub fn mark_stale_chunks(
+    changed_query: Query<(&Sector, &InChunk), Changed<Sector>>,
+    in_chunk_query: Query<&InChunk>,
+    mut chunk_query: Query<&mut MapChunk>,
+) {
+    let mut stale: HashSet<Entity> = HashSet::new();
+    for (sector, in_chunk) in &changed_query {
+        stale.insert(in_chunk.0);
+        stale.extend(
+            sector
+                .neighbors
+                .iter()
+                .filter_map(|e_neighbor| in_chunk_query.get(*e_neighbor).ok())
+                .map(|in_chunk| in_chunk.0),
+        );
+    }
+
+    for e_chunk in stale {
+        if let Ok(mut chunk) = chunk_query.get_mut(e_chunk) {
+            chunk.stale = true;
+        }
+    }
+}
+
+/// Rebuild the meshes of stale chunks that are in view. Chunks out of view are left stale
+/// until they come into view, and aren't drawn at all in the meantime.
+pub fn rebuild_map_chunks(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut chunk_query: Query<(&mut MapChunk, &Mesh2d, &ViewVisibility)>,
+    sector_query: Query<&Sector>,
+    biomes: Res<BiomeRegistry>,
+    palette: Res<MapPalette>,
+    overlay: Res<OverlayColors>,
+) {
+    for (mut chunk, mesh, visibility) in &mut chunk_query {
+        if !chunk.stale || !visibility.get() {
+            continue;
+        }
+
+        meshes.insert(
+            &mesh.0,
+            chunk_mesh(&chunk.sectors, &sector_query, &biomes, &palette, &overlay),
+        );
+        chunk.stale = false;
+    }
+}
+
+/// A mesh of the polygons of a group of sectors, colored by the overlay if there is one
+/// and by the palette otherwise.
+fn chunk_mesh(
+    sectors: &[Entity],
+    sector_query: &Query<&Sector>,
+    biomes: &BiomeRegistry,
+    palette: &MapPalette,
+    overlay: &OverlayColors,
+) -> Mesh {
+    // Data to be added to map mesh
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut triangles: Vec<u32> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+
+    for e_sector in sectors {
+        let sector = sector_query.get(*e_sector).unwrap();
+
+        let index_offset = positions.len() as u32;
+        for i in 1..sector.border.len() - 1 {
+            triangles.push(index_offset);
+            triangles.push(i as u32 + index_offset);
+            triangles.push(i as u32 + index_offset + 1);
+        }
+        let border_colors = match overlay.0.get(e_sector) {
+            Some(color) => vec![*color; sector.border.len()],
+            None => palette.border_colors(sector, sector_query, biomes),
+        };
+        for (vertex, color) in sector.border.iter().zip(border_colors) {
+            positions.push(vertex.extend(0.0));
+            colors.push(color.to_f32_array());
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+    .with_inserted_indices(Indices::U32(triangles))
+}
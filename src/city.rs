@@ -1,21 +1,27 @@
 use bevy::{platform::collections::HashMap, prelude::*};
-use bevy_prng::WyRand;
-use bevy_rand::prelude::*;
 use rand::Rng;
 
 use crate::{
-    biome::Biome,
-    city_names::get_name,
-    clickable::{ClickHitbox, ClickState, JustPressed},
+    biome::BiomeRegistry,
+    city_names::take_name,
     demographic::{Demographic, JobType, Population},
-    exposer_tags::ExposerTag,
-    format_text::{FormatText, ValueExposer},
-    map::{Map, Sector},
     settings::MapGenSettings,
     shipment::ShipmentReceiver,
+    spatial::PointGrid,
+    worldgen::{WorldCity, WorldSector},
+};
+#[cfg(feature = "render")]
+use crate::{
+    clickable::{ClickHitbox, ClickState, JustPressed},
+    exposer_tags::ExposerTag,
+    format_text::{FormatText, ValueExposer},
+    map::Sector,
     window::{EntryBuilder, WindowBuilder},
 };
 
+/// The radius cities are drawn and clicked with.
+pub const CITY_RADIUS: f32 = 10.0;
+
 #[derive(Component)]
 #[require(Transform, ShipmentReceiver)]
 pub struct City {
@@ -26,13 +32,18 @@ pub struct City {
     pub demographics: HashMap<JobType, Entity>,
 }
 
+#[cfg(feature = "render")]
 pub fn click_city(
     mut commands: Commands,
     query: Query<(Entity, &City, &Transform), With<JustPressed>>,
+    sector_query: Query<&Sector>,
 ) {
     let Ok((e_city, city, t_city)) = query.single() else {
         return;
     };
+    let coastal = sector_query
+        .get(city.sector)
+        .is_ok_and(|sector| sector.coastal);
 
     WindowBuilder::new()
         .width(Val::Percent(20.0))
@@ -43,6 +54,11 @@ pub fn click_city(
                 .add_text("Population: ")
                 .add_component_value(e_city, ExposerTag::CityPopulation),
         ))
+        .add_entry(EntryBuilder::text(if coastal {
+            "Coastal"
+        } else {
+            "Inland"
+        }))
         .add_entry(
             EntryBuilder::button("Open Subwindow")
                 .open_subwindow(
@@ -70,20 +86,22 @@ pub fn click_city(
         .spawn(&mut commands);
 }
 
-pub fn spawn_cities(
-    mut commands: Commands,
-    mut map_query: Query<(&Map, &mut Entropy<WyRand>)>,
-    sector_query: Query<&Sector>,
-    settings: Res<MapGenSettings>,
-) {
-    let (map, mut rng) = map_query.single_mut().unwrap();
-
-    let mut city_positions: Vec<Vec2> = Vec::new();
+/// Choose where cities go and how many people start in each.
+/// Names are drawn from `names` without repeats.
+pub fn place_cities(
+    sectors: &[WorldSector],
+    names: &[String],
+    settings: &MapGenSettings,
+    biomes: &BiomeRegistry,
+    rng: &mut impl Rng,
+) -> Vec<WorldCity> {
+    let mut unused_names = names.to_vec();
+    let mut cities: Vec<WorldCity> = Vec::new();
+    let mut placed = PointGrid::new(settings.city_min_spacing.max(1.0));
 
     for _ in 0..1000 {
-        let rand_index = rng.random_range(0..map.sectors.len());
-        let e_sector = map.sectors[rand_index];
-        let sector = sector_query.get(e_sector).unwrap();
+        let sector_index = rng.random_range(0..sectors.len());
+        let sector = &sectors[sector_index];
         let city_pos = sector.centroid;
 
         // Check not within deadzone
@@ -96,42 +114,44 @@ pub fn spawn_cities(
         }
 
         // Check not too close to another city
-        if city_positions.iter().any(|p| {
-            p.distance_squared(city_pos) < settings.city_min_spacing * settings.city_min_spacing
-        }) {
+        if placed
+            .within_radius(city_pos, settings.city_min_spacing)
+            .next()
+            .is_some()
+        {
             continue;
         }
 
         // Check in a valid biome
-        match sector.biome.unwrap() {
-            Biome::Forest | Biome::Mountains | Biome::Water => {
-                continue;
-            }
-            Biome::Plains | Biome::Desert => {}
+        if !biomes.get(sector.biome.unwrap()).cities {
+            continue;
         }
 
-        city_positions.push(city_pos.clone());
         let population = rng.random_range(settings.city_start_pop_range.clone());
 
-        spawn_city(
-            commands.reborrow(),
-            &mut rng,
-            e_sector,
-            city_pos,
+        placed.insert(city_pos, cities.len());
+        cities.push(WorldCity {
+            name: take_name(&mut unused_names, rng),
+            sector: sector_index,
+            position: city_pos,
             population,
-        );
+            resource_nodes: Vec::new(),
+        });
 
-        if city_positions.len() as u32 >= settings.city_num {
+        if cities.len() as u32 >= settings.city_num {
             break;
         }
     }
+
+    cities
 }
 
 /// Spawn a new city into the world at a sector and return it.
-fn spawn_city(
-    mut commands: Commands,
-    rng: &mut Entropy<WyRand>,
+pub fn spawn_city(
+    commands: &mut Commands,
+    name: String,
     sector: Entity,
+    resource_nodes: Vec<Entity>,
     position: Vec2,
     population: u32,
 ) -> Entity {
@@ -147,20 +167,15 @@ fn spawn_city(
     );
 
     let e_city = commands
-        .spawn((
+        .spawn(city_bundle(
             City {
-                name: get_name(rng),
-                resource_nodes: Vec::new(),
+                name,
+                resource_nodes,
                 sector,
                 demographics: demographics.clone(),
             },
-            Population { population },
-            Transform::from_xyz(position.x, position.y, 1.0),
-            ClickState::default(),
-            ClickHitbox::Circle { radius: 10.0 },
-            ValueExposer::default(),
-            Visibility::Visible,
-            ShipmentReceiver::new(),
+            population,
+            position,
         ))
         .id();
 
@@ -171,6 +186,19 @@ fn spawn_city(
     e_city
 }
 
+/// The components making up a city entity placed at a position.
+pub fn city_bundle(city: City, population: u32, position: Vec2) -> impl Bundle {
+    (
+        city,
+        Population { population },
+        Transform::from_xyz(position.x, position.y, 1.0),
+        Visibility::Visible,
+        ShipmentReceiver::new(),
+    )
+}
+
+/// Give every city a mesh and the components that let it be clicked on and displayed.
+#[cfg(feature = "render")]
 pub fn add_city_meshes(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -178,15 +206,22 @@ pub fn add_city_meshes(
     city_query: Query<Entity, With<City>>,
 ) {
     let material = materials.add(Color::BLACK);
-    let mesh = meshes.add(Circle::new(10.0));
+    let mesh = meshes.add(Circle::new(CITY_RADIUS));
 
     for e_city in city_query {
-        commands
-            .entity(e_city)
-            .insert((Mesh2d(mesh.clone()), MeshMaterial2d(material.clone())));
+        commands.entity(e_city).insert((
+            Mesh2d(mesh.clone()),
+            MeshMaterial2d(material.clone()),
+            ClickState::default(),
+            ClickHitbox::Circle {
+                radius: CITY_RADIUS,
+            },
+            ValueExposer::default(),
+        ));
     }
 }
 
+#[cfg(feature = "render")]
 pub fn expose_cities(query: Query<(&mut ValueExposer, &Population), Changed<City>>) {
     for (mut exposer, pop) in query {
         exposer
@@ -198,7 +233,7 @@ pub fn expose_cities(query: Query<(&mut ValueExposer, &Population), Changed<City
 pub fn receive_city_shipments(query: Query<(&City, &mut ShipmentReceiver)>) {
     for (city, mut receiver) in query {
         while let Some(shipment) = receiver.get_shipment() {
-            println!(
+            info!(
                 "{} received shipment of {} {:?}",
                 city.name, shipment.quantity, shipment.resource
             );
@@ -1,17 +1,10 @@
-use std::sync::Mutex;
+use std::{fs, io, path::Path};
 
 use bevy::{asset::AssetLoader, platform::collections::HashSet, prelude::*};
 use bevy_common_assets::csv::LoadedCsv;
-use bevy_prng::WyRand;
-use bevy_rand::prelude::Entropy;
-use lazy_static::lazy_static;
 use rand::Rng;
 use serde::Deserialize;
 
-lazy_static! {
-    static ref UNUSED_NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
-}
-
 #[derive(Asset, Reflect, Deserialize)]
 pub struct CityName {
     pub name: String,
@@ -24,20 +17,29 @@ pub fn load_name_list(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(NameListHandle(asset_server.load("city-names.csv")));
 }
 
-pub fn set_unused_names(name_list: Res<NameListHandle>, names: Res<Assets<LoadedCsv<CityName>>>) {
+/// Get every name in the loaded name list.
+pub fn all_names(name_list: &NameListHandle, names: &Assets<LoadedCsv<CityName>>) -> Vec<String> {
     let names_list_unwrapped = names.get(&name_list.0).unwrap();
-    let mut unused_names = UNUSED_NAMES.lock().unwrap();
-    for name in &names_list_unwrapped.rows {
-        unused_names.push(name.name.to_string());
-    }
+    names_list_unwrapped
+        .rows
+        .iter()
+        .map(|name| name.name.to_string())
+        .collect()
+}
+
+/// Read a name list straight from a CSV file, for use outside of the asset system.
+pub fn read_name_file(path: &Path) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .skip(1)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect())
 }
 
-/// Return a random name and mark it as having been used.
-pub fn get_name(rng: &mut Entropy<WyRand>) -> String {
-    let mut names = UNUSED_NAMES.lock().unwrap();
+/// Remove and return a random name from a list of unused names.
+pub fn take_name(names: &mut Vec<String>, rng: &mut impl Rng) -> String {
     let index = rng.random_range(..names.len());
-    let name = names[index].to_string();
-    names[index] = names.last().unwrap().to_string();
-    names.pop();
-    name
+    names.swap_remove(index)
 }
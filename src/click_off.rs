@@ -1,12 +1,17 @@
-use bevy::{prelude::*, ui::RelativeCursorPosition};
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::ui::RelativeCursorPosition;
 
+#[cfg(feature = "render")]
 use crate::window::Window;
 
 /// Component for UI elements that are killed when clicked off of.
+#[cfg(feature = "render")]
 #[derive(Component)]
 #[require(RelativeCursorPosition)]
 pub struct KillOnClickOff;
 
+#[cfg(feature = "render")]
 pub fn kill_on_click_off(
     mut commands: Commands,
     ui_query: Query<(Entity, &RelativeCursorPosition), (With<KillOnClickOff>, Without<Window>)>,
@@ -1,4 +1,5 @@
 use bevy::{platform::collections::HashSet, prelude::*};
+use serde::{Deserialize, Serialize};
 
 use crate::city::City;
 
@@ -16,7 +17,7 @@ pub struct Demographic {
 }
 
 /// The different jobs a demographic can have
-#[derive(Eq, PartialEq, Hash, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Serialize, Deserialize)]
 pub enum JobType {
     /// Politicians control the city's government
     Politician,
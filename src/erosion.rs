@@ -0,0 +1,248 @@
+use crate::{settings::MapGenSettings, worldgen::WorldSector};
+
+/// Wear down sector heights with `erosion_iters` rounds of hydraulic and thermal erosion.
+/// Rain runs downhill carrying sediment, cutting valleys where water gathers and dropping
+/// its load in pits and at the sea, then slopes steeper than the talus slope slump.
+/// No randomness is used, so the result only depends on the starting heights.
+pub fn erode(sectors: &mut [WorldSector], settings: &MapGenSettings) {
+    for _ in 0..settings.erosion_iters {
+        hydraulic_step(sectors, settings);
+        thermal_step(sectors, settings);
+    }
+}
+
+/// Rain one unit of water on every sector and route it, with its sediment, down the steepest
+/// slope. Sectors are visited from highest to lowest so each has received everything from
+/// upstream before passing it on. Slopes are measured on the heights from before the step,
+/// so a sector that has already been cut down can't start taking water from its neighbors.
+fn hydraulic_step(sectors: &mut [WorldSector], settings: &MapGenSettings) {
+    let heights: Vec<f32> = sectors.iter().map(|sector| sector.height).collect();
+    let mut order: Vec<usize> = (0..sectors.len()).collect();
+    order.sort_by(|a, b| heights[*b].total_cmp(&heights[*a]).then(a.cmp(b)));
+
+    let mut water = vec![1.0f32; sectors.len()];
+    let mut sediment = vec![0.0f32; sectors.len()];
+    for i in order {
+        let height = heights[i];
+        let downhill = sectors[i]
+            .neighbors
+            .iter()
+            .map(|j| {
+                let distance = sectors[i].centroid.distance(sectors[*j].centroid);
+                (*j, (height - heights[*j]) / distance.max(0.001))
+            })
+            .filter(|(_, slope)| *slope > 0.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)));
+
+        // Water reaching a pit or the sea stops and drops everything it carries
+        let Some((next, slope)) = downhill.filter(|_| height >= settings.water_cutoff) else {
+            sectors[i].height += sediment[i];
+            continue;
+        };
+
+        // Faster, fuller streams carry more, so valleys deepen where water gathers
+        let capacity = settings.sediment_capacity * water[i].sqrt() * slope;
+        if sediment[i] > capacity {
+            let deposit = (sediment[i] - capacity) * settings.deposition_rate;
+            sectors[i].height += deposit;
+            sediment[i] -= deposit;
+        } else {
+            // Never cut below the next sector down, or the stream would flow backwards
+            let drop = height - heights[next];
+            let eroded = ((capacity - sediment[i]) * settings.erosion_rate).min(drop / 2.0);
+            sectors[i].height -= eroded;
+            sediment[i] += eroded;
+        }
+
+        water[next] += water[i];
+        sediment[next] += sediment[i];
+    }
+}
+
+/// Move material from every slope steeper than `talus_slope` to the sector below it.
+/// All the moves are worked out before any are applied, so the order doesn't matter.
+fn thermal_step(sectors: &mut [WorldSector], settings: &MapGenSettings) {
+    let mut changes = vec![0.0f32; sectors.len()];
+    for (i, sector) in sectors.iter().enumerate() {
+        for j in sector.neighbors.iter().copied().filter(|j| *j > i) {
+            let distance = sector.centroid.distance(sectors[j].centroid);
+            let difference = sector.height - sectors[j].height;
+            let excess = difference.abs() - settings.talus_slope * distance;
+            if excess <= 0.0 {
+                continue;
+            }
+
+            // Moving half the excess would level the slope exactly
+            let moved = excess / 2.0 * settings.thermal_rate * difference.signum();
+            changes[i] -= moved;
+            changes[j] += moved;
+        }
+    }
+
+    for (sector, change) in sectors.iter_mut().zip(changes) {
+        sector.height += change;
+    }
+}
+
+/// Make steep land more expensive to cross, so roads keep to valleys and passes.
+pub fn add_slope_cost(sectors: &mut [WorldSector], settings: &MapGenSettings) {
+    if settings.slope_cost <= 0.0 {
+        return;
+    }
+
+    let slopes: Vec<f32> = sectors
+        .iter()
+        .map(|sector| {
+            sector
+                .neighbors
+                .iter()
+                .map(|j| {
+                    let distance = sector.centroid.distance(sectors[*j].centroid);
+                    (sector.height - sectors[*j].height).abs() / distance.max(0.001)
+                })
+                .fold(0.0, f32::max)
+        })
+        .collect();
+
+    for (sector, slope) in sectors.iter_mut().zip(slopes) {
+        if sector.height >= settings.water_cutoff {
+            sector.cost += slope * settings.slope_cost;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bevy::prelude::*;
+
+    use super::*;
+    use crate::{biome::BiomeRegistry, city_names, map_image::MapImages, worldgen};
+
+    /// A square grid of sectors one unit apart, each joined to the four beside it,
+    /// with bumpy heights that leave some pits and a strip of sea along one edge.
+    fn grid_sectors(size: usize) -> Vec<WorldSector> {
+        let index = |x: usize, y: usize| y * size + x;
+        (0..size * size)
+            .map(|i| {
+                let (x, y) = (i % size, i / size);
+                let centroid = vec2(x as f32, y as f32);
+                let mut neighbors = Vec::new();
+                if x > 0 {
+                    neighbors.push(index(x - 1, y));
+                }
+                if x + 1 < size {
+                    neighbors.push(index(x + 1, y));
+                }
+                if y > 0 {
+                    neighbors.push(index(x, y - 1));
+                }
+                if y + 1 < size {
+                    neighbors.push(index(x, y + 1));
+                }
+                WorldSector {
+                    site: centroid,
+                    border: Vec::new(),
+                    centroid,
+                    height: x as f32 * 0.15 - 1.0 + (y as f32 * 1.3).sin() * 0.3,
+                    biome: None,
+                    water_body: None,
+                    coastal: false,
+                    cost: 1.0,
+                    river_flow: 0.0,
+                    downstream: None,
+                    neighbors,
+                }
+            })
+            .collect()
+    }
+
+    fn heights(sectors: &[WorldSector]) -> Vec<f32> {
+        sectors.iter().map(|sector| sector.height).collect()
+    }
+
+    #[test]
+    fn hydraulic_erosion_moves_material_downhill() {
+        let settings = MapGenSettings::default();
+        let mut sectors = grid_sectors(12);
+        let before = heights(&sectors);
+        hydraulic_step(&mut sectors, &settings);
+        let after = heights(&sectors);
+
+        // Everything cut away is dropped again further down, and nothing is piled up
+        // above the highest point
+        let total = |heights: &[f32]| heights.iter().sum::<f32>();
+        assert!((total(&before) - total(&after)).abs() < 1e-3);
+        let peak = |heights: &[f32]| heights.iter().copied().fold(f32::MIN, f32::max);
+        assert!(peak(&after) <= peak(&before));
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn without_deposition_only_stream_ends_rise() {
+        let settings = MapGenSettings {
+            deposition_rate: 0.0,
+            ..MapGenSettings::default()
+        };
+        let mut sectors = grid_sectors(12);
+        let before = heights(&sectors);
+        hydraulic_step(&mut sectors, &settings);
+
+        // Sediment is only dropped where a stream ends, in the sea or in a pit
+        for (i, sector) in sectors.iter().enumerate() {
+            if sector.height <= before[i] {
+                continue;
+            }
+            let is_pit = sector.neighbors.iter().all(|j| before[*j] >= before[i]);
+            assert!(
+                before[i] < settings.water_cutoff || is_pit,
+                "sector {} rose from {} to {}",
+                i,
+                before[i],
+                sector.height
+            );
+        }
+    }
+
+    #[test]
+    fn thermal_erosion_conserves_height() {
+        let settings = MapGenSettings {
+            talus_slope: 0.01,
+            ..MapGenSettings::default()
+        };
+        let mut sectors = grid_sectors(12);
+        let before = heights(&sectors);
+        thermal_step(&mut sectors, &settings);
+        let after = heights(&sectors);
+
+        let total = |heights: &[f32]| heights.iter().sum::<f32>();
+        assert!((total(&before) - total(&after)).abs() < 1e-3);
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn eroded_worlds_are_deterministic() {
+        let settings = MapGenSettings {
+            sector_num: 2000,
+            erosion_iters: 10,
+            ..MapGenSettings::default()
+        };
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let names = city_names::read_name_file(Path::new("assets/city-names.csv")).unwrap();
+        let generate = |settings: &MapGenSettings| {
+            worldgen::generate_world(settings, &biomes, &MapImages::default(), 7, &names)
+                .fingerprint()
+        };
+
+        let eroded = generate(&settings);
+        assert_eq!(eroded, generate(&settings));
+
+        // Make sure erosion really ran
+        let uneroded = MapGenSettings {
+            erosion_iters: 0,
+            ..settings.clone()
+        };
+        assert_ne!(eroded, generate(&uneroded));
+    }
+}
@@ -0,0 +1,572 @@
+use std::{
+    fmt::{Display, Write},
+    fs, io,
+    path::Path,
+};
+
+use bevy::prelude::*;
+use image::{ImageError, ImageFormat, Rgb, RgbImage};
+use serde_json::{Value, json};
+
+use crate::{
+    biome::BiomeRegistry, city::CITY_RADIUS, resource::NODE_RADIUS, river::RIVER_COLOR,
+    road::ROAD_COLOR, settings::Settings, worldgen::GeneratedWorld,
+};
+
+/// How many points road curves are drawn with, the same as their meshes.
+const ROAD_RESOLUTION: usize = 150;
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Image(ImageError),
+
+    /// The file extension isn't `svg`, `png` or `geojson`.
+    UnknownFormat,
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Image(err) => write!(f, "{}", err),
+            Self::UnknownFormat => {
+                write!(f, "exported maps must end in .svg, .png or .geojson")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ImageError> for ExportError {
+    fn from(value: ImageError) -> Self {
+        Self::Image(value)
+    }
+}
+
+/// Write a generated world to an SVG or PNG image or a GeoJSON file,
+/// depending on the file extension.
+/// `scale` is the number of pixels per unit of map, and only affects PNGs.
+pub fn write_map(
+    path: &Path,
+    world: &GeneratedWorld,
+    settings: &Settings,
+    biomes: &BiomeRegistry,
+    scale: f32,
+) -> Result<(), ExportError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    match extension.as_deref() {
+        Some("svg") => fs::write(path, map_svg(world, settings, biomes))?,
+        Some("png") => {
+            map_png(world, settings, biomes, scale).save_with_format(path, ImageFormat::Png)?
+        }
+        Some("geojson") => fs::write(path, map_geojson(world, biomes).to_string())?,
+        _ => return Err(ExportError::UnknownFormat),
+    }
+    Ok(())
+}
+
+/// Every stretch of river between two sectors, with its width scaled by the flow like the
+/// river meshes.
+fn river_segments(world: &GeneratedWorld, settings: &Settings) -> Vec<(Vec2, Vec2, f32)> {
+    let max_flow = world
+        .sectors
+        .iter()
+        .map(|sector| sector.river_flow)
+        .fold(0.0, f32::max);
+    world
+        .sectors
+        .iter()
+        .filter(|sector| sector.river_flow > 0.0)
+        .filter_map(|sector| {
+            let next = &world.sectors[sector.downstream?];
+            let width = settings.display.river_width * (sector.river_flow / max_flow).sqrt();
+            Some((sector.centroid, next.centroid, width))
+        })
+        .collect()
+}
+
+/// Draw a generated world as an SVG document, with north at the top.
+pub fn map_svg(world: &GeneratedWorld, settings: &Settings, biomes: &BiomeRegistry) -> String {
+    let size = settings.map_gen.size;
+    let flip = |point: Vec2| vec2(point.x, size.y - point.y);
+    let hex = |(r, g, b): (u8, u8, u8)| format!("#{:02x}{:02x}{:02x}", r, g, b);
+
+    let mut svg = String::new();
+    // Writing to a string can't fail, so the results are ignored
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        size.x, size.y, size.x, size.y
+    );
+    let _ = writeln!(svg, "<title>Seed {}</title>", world.seed);
+
+    // Sectors are stroked in their own color so no background shows through the seams
+    let _ = writeln!(svg, r#"<g stroke-width="0.5" stroke-linejoin="round">"#);
+    for sector in &world.sectors {
+        let color = hex(biomes
+            .get(sector.biome.unwrap())
+            .map_color(sector.water_body));
+        let points: Vec<String> = sector
+            .border
+            .iter()
+            .map(|vertex| {
+                let vertex = flip(*vertex);
+                format!("{:.2},{:.2}", vertex.x, vertex.y)
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polygon points="{}" fill="{}" stroke="{}"/>"#,
+            points.join(" "),
+            color,
+            color
+        );
+    }
+    let _ = writeln!(svg, "</g>");
+
+    let _ = writeln!(
+        svg,
+        r#"<g stroke="{}" stroke-linecap="round">"#,
+        hex(RIVER_COLOR)
+    );
+    for (from, to, width) in river_segments(world, settings) {
+        let (from, to) = (flip(from), flip(to));
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke-width="{:.2}"/>"#,
+            from.x, from.y, to.x, to.y, width
+        );
+    }
+    let _ = writeln!(svg, "</g>");
+
+    let _ = writeln!(
+        svg,
+        r#"<g fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round">"#,
+        hex(ROAD_COLOR),
+        settings.display.road_width
+    );
+    for road in &world.roads {
+        let points: Vec<String> = road
+            .curve
+            .iter_positions(ROAD_RESOLUTION)
+            .map(|point| {
+                let point = flip(point);
+                format!("{:.2},{:.2}", point.x, point.y)
+            })
+            .collect();
+        let _ = writeln!(svg, r#"<polyline points="{}"/>"#, points.join(" "));
+    }
+    let _ = writeln!(svg, "</g>");
+
+    for node in &world.nodes {
+        let position = flip(node.position);
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{:.2}" cy="{:.2}" r="{}" fill="{}"/>"#,
+            position.x,
+            position.y,
+            NODE_RADIUS,
+            hex(node.node_type.color())
+        );
+    }
+
+    let _ = writeln!(svg, r#"<g font-family="serif" font-size="14">"#);
+    for city in &world.cities {
+        let position = flip(city.position);
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{:.2}" cy="{:.2}" r="{}" fill="black"/>"#,
+            position.x, position.y, CITY_RADIUS
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.2}" y="{:.2}" stroke="white" stroke-width="3" paint-order="stroke">{}</text>"#,
+            position.x + CITY_RADIUS + 2.0,
+            position.y + 5.0,
+            escape_xml(&city.name)
+        );
+    }
+    let _ = writeln!(svg, "</g>");
+
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Describe a generated world as a GeoJSON feature collection in map units, with y to the north.
+/// Sectors are polygons, cities and resource nodes are points and roads are line strings.
+/// Every feature has a `kind` property saying which of these it is, and references between
+/// features are indices into the world's lists of sectors, cities and nodes.
+pub fn map_geojson(world: &GeneratedWorld, biomes: &BiomeRegistry) -> Value {
+    let point = |point: Vec2| json!([point.x, point.y]);
+    let mut features: Vec<Value> = Vec::new();
+
+    for (index, sector) in world.sectors.iter().enumerate() {
+        // Polygon rings are closed and their exterior runs counterclockwise
+        let mut ring: Vec<Vec2> = sector.border.clone();
+        if signed_area(&ring) < 0.0 {
+            ring.reverse();
+        }
+        if let Some(first) = ring.first().copied() {
+            ring.push(first);
+        }
+
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Polygon",
+                "coordinates": [ring.into_iter().map(point).collect::<Vec<Value>>()],
+            },
+            "properties": {
+                "kind": "sector",
+                "index": index,
+                "height": sector.height,
+                "biome": sector.biome.map(|biome| biomes.get(biome).name.clone()),
+                "water_body": sector.water_body,
+                "coastal": sector.coastal,
+                "cost": sector.cost,
+                "river_flow": sector.river_flow,
+            },
+        }));
+    }
+
+    for (index, city) in world.cities.iter().enumerate() {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": point(city.position) },
+            "properties": {
+                "kind": "city",
+                "index": index,
+                "name": city.name,
+                "population": city.population,
+                "sector": city.sector,
+            },
+        }));
+    }
+
+    for (index, node) in world.nodes.iter().enumerate() {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": point(node.position) },
+            "properties": {
+                "kind": "resource_node",
+                "index": index,
+                "node_type": node.node_type,
+                "produces": node.produces,
+                "city": node.city,
+                "sector": node.sector,
+            },
+        }));
+    }
+
+    for (index, road) in world.roads.iter().enumerate() {
+        let line: Vec<Value> = road
+            .curve
+            .iter_positions(ROAD_RESOLUTION)
+            .map(point)
+            .collect();
+        let node = world.nodes.iter().position(|node| node.road == Some(index));
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": line },
+            "properties": {
+                "kind": "road",
+                "index": index,
+                "city": node.map(|node| world.nodes[node].city),
+                "node": node,
+                "start_sector": road.start_sector,
+                "end_sector": road.end_sector,
+                "length": road.length,
+            },
+        }));
+    }
+
+    json!({
+        "type": "FeatureCollection",
+        "seed": world.seed,
+        "features": features,
+    })
+}
+
+/// Twice the area enclosed by a polygon, positive if its points run counterclockwise.
+fn signed_area(points: &[Vec2]) -> f32 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum()
+}
+
+/// Draw a generated world as an image, with north at the top.
+/// City names are left out, as there's no font to draw them with.
+pub fn map_png(
+    world: &GeneratedWorld,
+    settings: &Settings,
+    biomes: &BiomeRegistry,
+    scale: f32,
+) -> RgbImage {
+    let size = settings.map_gen.size;
+    let mut canvas = Canvas {
+        image: RgbImage::new(
+            (size.x * scale).ceil() as u32,
+            (size.y * scale).ceil() as u32,
+        ),
+        map_height: size.y,
+        scale,
+    };
+
+    for sector in &world.sectors {
+        let color = biomes
+            .get(sector.biome.unwrap())
+            .map_color(sector.water_body);
+        canvas.fill_polygon(&sector.border, color);
+    }
+    for (from, to, width) in river_segments(world, settings) {
+        canvas.draw_line(&[from, to], width, RIVER_COLOR);
+    }
+    for road in &world.roads {
+        let points: Vec<Vec2> = road.curve.iter_positions(ROAD_RESOLUTION).collect();
+        canvas.draw_line(&points, settings.display.road_width, ROAD_COLOR);
+    }
+    for node in &world.nodes {
+        canvas.fill_circle(node.position, NODE_RADIUS, node.node_type.color());
+    }
+    for city in &world.cities {
+        canvas.fill_circle(city.position, CITY_RADIUS, (0, 0, 0));
+    }
+
+    canvas.image
+}
+
+/// A very small rasterizer that draws in map units. Pixels are either covered or not,
+/// with no antialiasing, which is plenty for reviewing a map.
+struct Canvas {
+    image: RgbImage,
+    map_height: f32,
+    scale: f32,
+}
+
+impl Canvas {
+    fn to_pixel(&self, point: Vec2) -> Vec2 {
+        vec2(point.x, self.map_height - point.y) * self.scale
+    }
+
+    /// Set every pixel whose center passes `covered` within a box of pixels.
+    fn fill_where(
+        &mut self,
+        min: Vec2,
+        max: Vec2,
+        color: (u8, u8, u8),
+        covered: impl Fn(Vec2) -> bool,
+    ) {
+        let (width, height) = self.image.dimensions();
+        let min = min.floor().max(Vec2::ZERO);
+        let max = max.ceil().min(vec2(width as f32, height as f32));
+        for y in min.y as u32..max.y as u32 {
+            for x in min.x as u32..max.x as u32 {
+                if covered(vec2(x as f32 + 0.5, y as f32 + 0.5)) {
+                    self.image.put_pixel(x, y, Rgb([color.0, color.1, color.2]));
+                }
+            }
+        }
+    }
+
+    fn fill_polygon(&mut self, border: &[Vec2], color: (u8, u8, u8)) {
+        let points: Vec<Vec2> = border.iter().map(|point| self.to_pixel(*point)).collect();
+        let Some((min, max)) = bounds(&points) else {
+            return;
+        };
+
+        // Even-odd rule: a point is inside if a ray from it crosses the border an odd number of times
+        self.fill_where(min, max, color, |pixel| {
+            let mut inside = false;
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                if (a.y > pixel.y) != (b.y > pixel.y)
+                    && pixel.x < a.x + (pixel.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                {
+                    inside = !inside;
+                }
+            }
+            inside
+        });
+    }
+
+    fn fill_circle(&mut self, center: Vec2, radius: f32, color: (u8, u8, u8)) {
+        let center = self.to_pixel(center);
+        let radius = radius * self.scale;
+        self.fill_where(center - radius, center + radius, color, |pixel| {
+            pixel.distance(center) <= radius
+        });
+    }
+
+    fn draw_line(&mut self, path: &[Vec2], width: f32, color: (u8, u8, u8)) {
+        let half_width = (width * self.scale / 2.0).max(0.5);
+        let points: Vec<Vec2> = path.iter().map(|point| self.to_pixel(*point)).collect();
+        for segment in points.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            self.fill_where(
+                a.min(b) - half_width,
+                a.max(b) + half_width,
+                color,
+                |pixel| {
+                    let along = b - a;
+                    let t = ((pixel - a).dot(along) / along.length_squared().max(f32::EPSILON))
+                        .clamp(0.0, 1.0);
+                    pixel.distance(a + along * t) <= half_width
+                },
+            );
+        }
+    }
+}
+
+/// The corners of the smallest box containing every point, or `None` if there are none.
+fn bounds(points: &[Vec2]) -> Option<(Vec2, Vec2)> {
+    let first = *points.first()?;
+    Some(points.iter().fold((first, first), |(min, max), point| {
+        (min.min(*point), max.max(*point))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::{city_names, map_image::MapImages, settings::MapGenSettings, worldgen};
+
+    /// Settings for a small map, with the world generated from them.
+    fn small_world(biomes: &BiomeRegistry) -> (Settings, GeneratedWorld) {
+        let settings = Settings {
+            map_gen: MapGenSettings {
+                sector_num: 2000,
+                ..MapGenSettings::default()
+            },
+            ..Settings::default()
+        };
+        let names = city_names::read_name_file(Path::new("assets/city-names.csv")).unwrap();
+        let world =
+            worldgen::generate_world(&settings.map_gen, biomes, &MapImages::default(), 7, &names);
+        (settings, world)
+    }
+
+    #[test]
+    fn svg_has_a_polygon_per_sector() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let (settings, world) = small_world(&biomes);
+
+        let svg = map_svg(&world, &settings, &biomes);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<polygon").count(), world.sectors.len());
+        assert_eq!(svg.matches("<polyline").count(), world.roads.len());
+        for city in &world.cities {
+            assert!(svg.contains(&escape_xml(&city.name)));
+        }
+    }
+
+    #[test]
+    fn png_is_the_map_size_times_the_scale() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let (settings, world) = small_world(&biomes);
+
+        let image = map_png(&world, &settings, &biomes, 0.5);
+        let size = settings.map_gen.size * 0.5;
+        assert_eq!(image.width(), size.x.ceil() as u32);
+        assert_eq!(image.height(), size.y.ceil() as u32);
+    }
+
+    #[test]
+    fn unknown_extensions_are_rejected() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let (settings, world) = small_world(&biomes);
+
+        let path = env::temp_dir().join(format!("rome-game-export-{}.txt", process::id()));
+        let result = write_map(&path, &world, &settings, &biomes, 1.0);
+        assert!(matches!(result, Err(ExportError::UnknownFormat)));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn geojson_round_trips_every_feature() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let (settings, world) = small_world(&biomes);
+
+        let path = env::temp_dir().join(format!("rome-game-export-{}.GeoJSON", process::id()));
+        write_map(&path, &world, &settings, &biomes, 1.0).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let geojson: Value = serde_json::from_str(&text).unwrap();
+
+        assert_eq!(geojson["type"], "FeatureCollection");
+        assert_eq!(geojson["seed"], world.seed);
+        let features = geojson["features"].as_array().unwrap();
+        let of_kind = |kind: &str| -> Vec<&Value> {
+            features
+                .iter()
+                .filter(|feature| feature["properties"]["kind"] == kind)
+                .collect()
+        };
+        let (sectors, cities, nodes, roads) = (
+            of_kind("sector"),
+            of_kind("city"),
+            of_kind("resource_node"),
+            of_kind("road"),
+        );
+        assert_eq!(sectors.len(), world.sectors.len());
+        assert_eq!(cities.len(), world.cities.len());
+        assert_eq!(nodes.len(), world.nodes.len());
+        assert_eq!(roads.len(), world.roads.len());
+        assert_eq!(
+            features.len(),
+            sectors.len() + cities.len() + nodes.len() + roads.len()
+        );
+
+        for (feature, sector) in sectors.iter().zip(&world.sectors) {
+            let properties = &feature["properties"];
+            assert_eq!(feature["geometry"]["type"], "Polygon");
+            assert_eq!(properties["height"].as_f64().unwrap() as f32, sector.height);
+            assert_eq!(properties["cost"].as_f64().unwrap() as f32, sector.cost);
+            match sector.biome {
+                Some(biome) => assert_eq!(properties["biome"], biomes.get(biome).name.as_str()),
+                None => assert!(properties["biome"].is_null()),
+            }
+            let ring = feature["geometry"]["coordinates"][0].as_array().unwrap();
+            assert_eq!(ring.first(), ring.last());
+        }
+        for (feature, city) in cities.iter().zip(&world.cities) {
+            assert_eq!(feature["geometry"]["type"], "Point");
+            assert_eq!(feature["properties"]["name"], city.name.as_str());
+            assert_eq!(feature["properties"]["population"], city.population);
+        }
+        for (feature, node) in nodes.iter().zip(&world.nodes) {
+            assert_eq!(feature["geometry"]["type"], "Point");
+            assert_eq!(feature["properties"]["node_type"], json!(node.node_type));
+            assert_eq!(feature["properties"]["city"], node.city);
+        }
+        for feature in &roads {
+            assert_eq!(feature["geometry"]["type"], "LineString");
+            let line = feature["geometry"]["coordinates"].as_array().unwrap();
+            // Both ends of every subdivision
+            assert_eq!(line.len(), ROAD_RESOLUTION + 1);
+            assert!(
+                line.iter()
+                    .all(|point| point.as_array().unwrap().len() == 2)
+            );
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::{collections::BTreeMap, fmt::Display, fs, io, path::Path};
+
+use bevy::prelude::*;
+
+use crate::{
+    biome::BiomeRegistry,
+    map_image::MapImages,
+    settings::MapGenSettings,
+    worldgen::{self, GeneratedWorld},
+};
+
+/// Where the known good fingerprints are stored, relative to the working directory.
+pub const GOLDEN_PATH: &str = "assets/fingerprints.ron";
+
+/// Builds a 64-bit FNV-1a hash that is the same on every build and platform,
+/// unlike [`std::hash::DefaultHasher`]. Numbers are always hashed as little endian bytes.
+pub struct StableHasher {
+    hash: u64,
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StableHasher {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    pub fn new() -> StableHasher {
+        StableHasher {
+            hash: Self::OFFSET_BASIS,
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash = (self.hash ^ *byte as u64).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    pub fn write_vec2(&mut self, value: Vec2) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+    }
+
+    /// Hash a string, prefixed by its length so neighboring strings can't run together.
+    pub fn write_str(&mut self, value: &str) {
+        self.write_usize(value.len());
+        self.write(value.as_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl GeneratedWorld {
+    /// A stable hash of everything generation decides: sector sites, heights, biomes, water and rivers,
+    /// city positions and names, node placements, road paths and provinces.
+    /// Two worlds generated from the same seed and settings must have the same fingerprint.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write_u64(self.seed);
+
+        hasher.write_usize(self.sectors.len());
+        for sector in &self.sectors {
+            hasher.write_vec2(sector.site);
+            hasher.write_f32(sector.height);
+            hasher.write_u32(sector.biome.map_or(0, |biome| biome.0 as u32 + 1));
+            hasher.write_u32(sector.water_body.map_or(0, |body| body as u32 + 1));
+            hasher.write_u32(sector.coastal as u32);
+            hasher.write_f32(sector.river_flow);
+            hasher.write_usize(sector.downstream.map_or(0, |i| i + 1));
+            hasher.write_usize(sector.neighbors.len());
+            for neighbor in &sector.neighbors {
+                hasher.write_usize(*neighbor);
+            }
+        }
+
+        hasher.write_usize(self.cities.len());
+        for city in &self.cities {
+            hasher.write_str(&city.name);
+            hasher.write_vec2(city.position);
+            hasher.write_usize(city.sector);
+            hasher.write_u32(city.population);
+        }
+
+        hasher.write_usize(self.nodes.len());
+        for node in &self.nodes {
+            hasher.write_u32(node.node_type as u32);
+            hasher.write_u32(node.produces as u32);
+            hasher.write_vec2(node.position);
+            hasher.write_usize(node.sector);
+            hasher.write_usize(node.city);
+        }
+
+        hasher.write_usize(self.roads.len());
+        for road in &self.roads {
+            hasher.write_usize(road.path.len());
+            for sector in &road.path {
+                hasher.write_usize(*sector);
+            }
+            hasher.write_f32(road.length);
+        }
+
+        hasher.write_usize(self.provinces.len());
+        for province in &self.provinces {
+            hasher.write_str(&province.name);
+            hasher.write_usize(province.capital.map_or(0, |i| i + 1));
+            hasher.write_usize(province.sectors.len());
+            for sector in &province.sectors {
+                hasher.write_usize(*sector);
+            }
+        }
+
+        hasher.finish()
+    }
+}
+
+/// The fingerprint of the world generated from a seed with the default [`MapGenSettings`].
+/// Golden fingerprints use the defaults so tweaking the settings file doesn't invalidate them.
+pub fn fingerprint_seed(seed: u64, names: &[String], biomes: &BiomeRegistry) -> u64 {
+    let settings = MapGenSettings::default();
+    worldgen::generate_world(&settings, biomes, &MapImages::default(), seed, names).fingerprint()
+}
+
+#[derive(Debug)]
+pub enum FingerprintError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl Display for FingerprintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Format(err) => write!(f, "invalid fingerprint file: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for FingerprintError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// Known good fingerprints by seed. A seed maps to `None` until its fingerprint is recorded.
+pub type GoldenFingerprints = BTreeMap<u64, Option<u64>>;
+
+pub fn read_golden(path: &Path) -> Result<GoldenFingerprints, FingerprintError> {
+    let text = fs::read_to_string(path)?;
+    ron::from_str(&text).map_err(|err| FingerprintError::Format(err.to_string()))
+}
+
+pub fn write_golden(path: &Path, golden: &GoldenFingerprints) -> Result<(), FingerprintError> {
+    let text = ron::ser::to_string_pretty(golden, ron::ser::PrettyConfig::default())
+        .map_err(|err| FingerprintError::Format(err.to_string()))?;
+    fs::write(path, text + "\n")?;
+    Ok(())
+}
+
+/// The result of checking one seed against its golden fingerprint.
+pub enum GoldenCheck {
+    Matches,
+    Differs { expected: u64, actual: u64 },
+    NotRecorded { actual: u64 },
+}
+
+/// Regenerate the world for every golden seed and compare it with the recorded fingerprint.
+pub fn check_golden(
+    golden: &GoldenFingerprints,
+    names: &[String],
+    biomes: &BiomeRegistry,
+) -> Vec<(u64, GoldenCheck)> {
+    golden
+        .iter()
+        .map(|(seed, expected)| {
+            let actual = fingerprint_seed(*seed, names, biomes);
+            let check = match expected {
+                Some(expected) if *expected == actual => GoldenCheck::Matches,
+                Some(expected) => GoldenCheck::Differs {
+                    expected: *expected,
+                    actual,
+                },
+                None => GoldenCheck::NotRecorded { actual },
+            };
+            (*seed, check)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::city_names;
+
+    #[test]
+    fn golden_fingerprints_match() {
+        let golden = read_golden(Path::new(GOLDEN_PATH)).unwrap();
+        let names = city_names::read_name_file(Path::new("assets/city-names.csv")).unwrap();
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        assert!(!golden.is_empty());
+
+        for (seed, check) in check_golden(&golden, &names, &biomes) {
+            match check {
+                GoldenCheck::Matches => {}
+                GoldenCheck::Differs { expected, actual } => panic!(
+                    "seed {}: fingerprint {:016x} doesn't match golden {:016x}, \
+                     run with --record-fingerprints if the change is intended",
+                    seed, actual, expected
+                ),
+                GoldenCheck::NotRecorded { actual } => {
+                    panic!("seed {}: fingerprint {:016x} not recorded", seed, actual)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+use bevy::{
+    app::PluginsState, log::LogPlugin, prelude::*, state::app::StatesPlugin,
+    time::TimeUpdateStrategy,
+};
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{
+    SimulationPlugin, city::City, click_off::Despawning, demographic::Population, history::History,
+    map::Map, resource::Resource, shipment::ShipmentReceiver, states::AppState, wagon::Wagon,
+};
+
+/// How much simulated time passes in each headless tick.
+const TICK_LENGTH: Duration = Duration::from_secs(1);
+
+/// Runs [`SimulationPlugin`] without a window for a fixed number of ticks,
+/// then prints or writes statistics about the final state.
+/// Logs go to stderr, so printed statistics can be piped straight into a JSON parser.
+pub struct HeadlessPlugin {
+    /// How many ticks of [`TICK_LENGTH`] to simulate.
+    pub ticks: u32,
+
+    /// A file to write the statistics to as JSON, or `None` to print them.
+    pub stats: Option<PathBuf>,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let ticks = self.ticks;
+        let stats = self.stats.clone();
+
+        app.add_plugins((
+            MinimalPlugins,
+            LogPlugin::default(),
+            TransformPlugin,
+            AssetPlugin::default(),
+            StatesPlugin,
+            SimulationPlugin,
+        ))
+        .set_runner(move |app| run_headless(app, ticks, stats));
+    }
+}
+
+/// Statistics about the state of the simulation at the end of a headless run.
+#[derive(Serialize)]
+pub struct SimulationStats {
+    pub seed: Option<u64>,
+    pub ticks: u32,
+    pub date: Option<NaiveDate>,
+    pub cities: Vec<CityStats>,
+    pub wagons_in_flight: usize,
+}
+
+#[derive(Serialize)]
+pub struct CityStats {
+    pub name: String,
+    pub population: u32,
+    pub shipments_received: BTreeMap<Resource, u32>,
+}
+
+/// App runner that steps the simulation on a fixed clock.
+fn run_headless(mut app: App, ticks: u32, stats_path: Option<PathBuf>) -> AppExit {
+    while app.plugins_state() == PluginsState::Adding {
+        bevy::tasks::tick_global_task_pools_on_main_thread();
+    }
+    app.finish();
+    app.cleanup();
+
+    // Every update advances the clock by exactly one tick, however long it really took
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(TICK_LENGTH));
+    app.world_mut()
+        .resource_mut::<Time<Virtual>>()
+        .set_max_delta(TICK_LENGTH);
+
+    // Wait for assets to load and the world to be set up before counting ticks
+    while *app.world().resource::<State<AppState>>().get() != AppState::InGame {
+        app.update();
+        if let Some(exit) = app.should_exit() {
+            return exit;
+        }
+    }
+
+    for _ in 0..ticks {
+        app.update();
+        if let Some(exit) = app.should_exit() {
+            return exit;
+        }
+    }
+
+    let stats = collect_stats(app.world_mut(), ticks);
+    let json = serde_json::to_string_pretty(&stats).expect("Failed to serialize statistics");
+    match stats_path {
+        Some(path) => {
+            if let Err(err) = fs::write(&path, json) {
+                error!("Failed to write statistics to {}: {err}", path.display());
+                return AppExit::error();
+            }
+        }
+        None => println!("{json}"),
+    }
+
+    AppExit::Success
+}
+
+/// Gather statistics about the current state of the simulation.
+pub fn collect_stats(world: &mut World, ticks: u32) -> SimulationStats {
+    let seed = world.query::<&Map>().single(world).ok().map(|map| map.seed);
+    let date = world
+        .query::<&History>()
+        .single(world)
+        .ok()
+        .map(|history| history.date);
+
+    let mut cities: Vec<CityStats> = world
+        .query::<(&City, &Population, &ShipmentReceiver)>()
+        .iter(world)
+        .map(|(city, population, receiver)| CityStats {
+            name: city.name.clone(),
+            population: population.population,
+            shipments_received: receiver.total_received().clone(),
+        })
+        .collect();
+    cities.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let wagons_in_flight = world
+        .query_filtered::<(), (With<Wagon>, Without<Despawning>)>()
+        .iter(world)
+        .count();
+
+    SimulationStats {
+        seed,
+        ticks,
+        date,
+        cities,
+        wagons_in_flight,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::{GameConfig, settings::Settings};
+
+    #[test]
+    fn headless_runs_start_and_write_statistics() {
+        let mut settings = Settings::default();
+        settings.map_gen.sector_num = 2000;
+        let path = env::temp_dir().join(format!("rome-game-stats-{}.json", process::id()));
+
+        // Run the way the binary does, so the app has to get through loading on its own
+        let mut app = App::new();
+        app.insert_resource(GameConfig {
+            seed: Some(1),
+            settings,
+            ..GameConfig::default()
+        })
+        .add_plugins(HeadlessPlugin {
+            ticks: 2,
+            stats: Some(path.clone()),
+        });
+        assert_eq!(app.run(), AppExit::Success);
+
+        let stats: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(stats["seed"], 1);
+        assert_eq!(stats["ticks"], 2);
+        assert!(!stats["cities"].as_array().unwrap().is_empty());
+    }
+}
@@ -2,23 +2,33 @@ use bevy::{
     platform::collections::HashMap, prelude::*, render::render_resource::encase::private::Length,
 };
 use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
 
-use crate::{city::City, map::Sector, utils::bezier_pathfind};
+use crate::{city::City, map::Sector, settings::GameplaySettings, utils::bezier_pathfind};
+
+/// The in-game date a new map starts on.
+pub const START_DATE: NaiveDate = NaiveDate::from_ymd_opt(-300, 1, 1).unwrap();
 
 /// Information about the history of the whole map.
 #[derive(Component)]
 pub struct History {
-    events: Vec<HistoricalEvent>,
+    pub events: Vec<HistoricalEvent>,
+
+    /// The current in-game date.
+    pub date: NaiveDate,
+
+    /// How far through the current day the game is, from 0 to 1.
+    pub day_progress: f32,
 }
 
 /// Information about the society and culture of a city.
 #[derive(Component)]
 pub struct CultureInfo {
-    neighbors: HashMap<Entity, NeighborInfo>,
+    pub neighbors: HashMap<Entity, NeighborInfo>,
 }
 
 impl CultureInfo {
-    fn new(neighbors: HashMap<Entity, NeighborInfo>) -> Self {
+    pub fn new(neighbors: HashMap<Entity, NeighborInfo>) -> Self {
         CultureInfo { neighbors }
     }
 }
@@ -26,26 +36,27 @@ impl CultureInfo {
 /// Information about a single event in history.
 /// Intended to be a singleton component.
 pub struct HistoricalEvent {
-    event_type: EventType,
-    date: NaiveDate,
+    pub event_type: EventType,
+    pub date: NaiveDate,
 
     /// List of involved cities
-    involved: Vec<Entity>,
+    pub involved: Vec<Entity>,
 }
 
 /// Type of historical event.
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum EventType {}
 
 /// Information about a city's relationship with one of its neighbors.
 #[derive(Debug)]
 pub struct NeighborInfo {
-    neighbor: Entity,
-    distance: f32,
-    road_distance: f32,
+    pub neighbor: Entity,
+    pub distance: f32,
+    pub road_distance: f32,
 }
 
 impl NeighborInfo {
-    fn new(entity: Entity, distance: f32, road_distance: f32) -> Self {
+    pub fn new(entity: Entity, distance: f32, road_distance: f32) -> Self {
         NeighborInfo {
             neighbor: entity,
             distance,
@@ -75,7 +86,7 @@ fn find_neighbors(
         let this_sector = q_sector.get(this_e_sector).unwrap();
         let sector = q_sector.get(e_sector).unwrap();
 
-        let (curve, _) = bezier_pathfind(this_e_sector, e_sector, &q_sector).unwrap();
+        let (curve, _) = bezier_pathfind(this_e_sector, e_sector, q_sector).unwrap();
 
         neighbors.insert(
             e_city,
@@ -101,7 +112,11 @@ pub fn setup_history_sim(
         commands.entity(e_city).insert(CultureInfo::new(neighbors));
     }
 
-    commands.spawn(History { events: Vec::new() });
+    commands.spawn(History {
+        events: Vec::new(),
+        date: START_DATE,
+        day_progress: 0.0,
+    });
 }
 
 /// Run the history simulation.
@@ -111,3 +126,20 @@ pub fn run_history_sim(
 ) {
     let mut history = q_history.single_mut().unwrap();
 }
+
+/// Move the in-game date forward.
+pub fn advance_date(
+    time: Res<Time>,
+    settings: Res<GameplaySettings>,
+    mut q_history: Query<&mut History>,
+) {
+    let Ok(mut history) = q_history.single_mut() else {
+        return;
+    };
+
+    history.day_progress += time.delta_secs() * settings.days_per_second;
+    while history.day_progress >= 1.0 {
+        history.day_progress -= 1.0;
+        history.date = history.date.succ_opt().unwrap();
+    }
+}
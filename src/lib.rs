@@ -0,0 +1,295 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::{
+    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    input::{InputSystem, common_conditions::input_just_pressed},
+    render::view::VisibilitySystems,
+};
+use bevy_common_assets::{csv::CsvAssetPlugin, ron::RonAssetPlugin};
+use bevy_prng::WyRand;
+use bevy_rand::plugin::EntropyPlugin;
+
+pub mod biome;
+#[cfg(feature = "render")]
+pub mod chunk;
+pub mod city;
+pub mod city_names;
+pub mod click_off;
+#[cfg(feature = "render")]
+pub mod clickable;
+pub mod demographic;
+pub mod erosion;
+pub mod export;
+#[cfg(feature = "render")]
+pub mod exposer_tags;
+pub mod fingerprint;
+#[cfg(feature = "render")]
+pub mod format_text;
+pub mod headless;
+pub mod history;
+#[cfg(feature = "render")]
+mod keyboard;
+pub mod map;
+pub mod map_image;
+pub mod migration;
+#[cfg(feature = "render")]
+pub mod mouse;
+#[cfg(feature = "render")]
+pub mod overlay;
+#[cfg(feature = "render")]
+pub mod pointer_capture;
+pub mod province;
+pub mod resource;
+pub mod river;
+pub mod road;
+pub mod save;
+pub mod seed;
+pub mod settings;
+pub mod shading;
+pub mod shape;
+pub mod shipment;
+pub mod spatial;
+pub mod states;
+#[cfg(feature = "render")]
+pub mod ui;
+pub mod utils;
+pub mod wagon;
+pub mod water;
+#[cfg(feature = "render")]
+pub mod window;
+pub mod worldgen;
+
+/// Options deciding how the game starts.
+/// Insert this before adding [`SimulationPlugin`] or [`GamePlugin`], otherwise the defaults are used.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct GameConfig {
+    /// The seed to use for map generation, or `None` to pick one at random.
+    pub seed: Option<u64>,
+
+    /// A save to load instead of generating a new map.
+    pub load: Option<PathBuf>,
+
+    /// Display debug gizmos. Only used with the `debug` feature.
+    pub debug: bool,
+
+    /// Display node connections. Only used with the `debug` feature.
+    pub debug_relations: bool,
+
+    /// Display road gizmos. Only used with the `debug` feature.
+    pub debug_roads: bool,
+
+    /// Display performance metrics.
+    pub performance: bool,
+
+    /// The settings to start the game with.
+    pub settings: settings::Settings,
+}
+
+/// Systems that build the world when the game enters [`states::AppState::InGame`].
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WorldSetup;
+
+/// The game simulation, without any rendering, UI or input.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world_mut().get_resource_or_init::<GameConfig>().clone();
+
+        app.add_plugins((
+            EntropyPlugin::<WyRand>::default(),
+            CsvAssetPlugin::<city_names::CityName>::new(&["csv"]),
+            RonAssetPlugin::<biome::BiomeRegistry>::new(&["biomes.ron"]),
+        ));
+
+        // A new map is only generated if there's no save to load, or it couldn't be loaded
+        app.add_systems(
+            OnEnter(states::AppState::InGame),
+            (
+                save::load_game,
+                (map::create_map, map::generate_map)
+                    .chain()
+                    .run_if(not(any_with_component::<map::Map>)),
+                spatial::build_spatial_index,
+                (history::setup_history_sim, history::run_history_sim)
+                    .chain()
+                    .run_if(not(any_with_component::<history::History>)),
+                save::spawn_save_manager,
+            )
+                .chain()
+                .in_set(WorldSetup),
+        );
+
+        app.insert_state(states::AppState::Loading)
+            .add_event::<save::SaveRequest>()
+            .init_resource::<wagon::SectorTraffic>()
+            .insert_resource(config.settings.map_gen.clone())
+            .insert_resource(config.settings.gameplay.clone())
+            .insert_resource(config.settings.save.clone())
+            .add_systems(
+                OnEnter(states::AppState::InGame),
+                biome::insert_biome_registry.before(WorldSetup),
+            )
+            .add_systems(
+                Startup,
+                (city_names::load_name_list, biome::load_biome_list),
+            )
+            .add_systems(
+                Update,
+                (
+                    demographic::update_demographics,
+                    demographic::update_city_pop.after(demographic::update_demographics),
+                    wagon::move_wagons,
+                    wagon::record_traffic,
+                    city::receive_city_shipments.after(wagon::move_wagons),
+                    resource::spawn_node_wagons,
+                    save::tick_save_manager.run_if(in_state(states::AppState::InGame)),
+                    history::advance_date,
+                ),
+            )
+            .add_systems(PostUpdate, click_off::despawn)
+            .add_systems(
+                Update,
+                states::check_loaded.run_if(in_state(states::AppState::Loading)),
+            );
+    }
+}
+
+/// The full game: the simulation plus rendering, UI and input.
+#[cfg(feature = "render")]
+pub struct GamePlugin;
+
+#[cfg(feature = "render")]
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(SimulationPlugin);
+        let config = app.world().resource::<GameConfig>().clone();
+
+        #[cfg(feature = "debug")]
+        add_debug_systems(app, &config);
+
+        if config.performance {
+            app.add_plugins((
+                LogDiagnosticsPlugin::default(),
+                FrameTimeDiagnosticsPlugin::default(),
+                bevy::diagnostic::SystemInformationDiagnosticsPlugin,
+            ));
+        }
+
+        app.init_resource::<mouse::MousePos>()
+            .init_resource::<pointer_capture::IsPointerCaptured>()
+            .init_resource::<overlay::ActiveOverlay>()
+            .init_resource::<overlay::OverlayColors>()
+            .insert_resource(config.settings.display.clone())
+            .add_systems(
+                OnEnter(states::AppState::InGame),
+                (
+                    chunk::add_map_chunks,
+                    city::add_city_meshes,
+                    resource::add_node_meshes,
+                    road::add_road_meshes,
+                    river::add_river_meshes,
+                    province::add_province_meshes,
+                    map::add_seed_label,
+                )
+                    .after(WorldSetup),
+            )
+            .add_systems(Startup, add_camera)
+            .add_systems(
+                PreUpdate,
+                (
+                    mouse::update_mouse_pos,
+                    pointer_capture::update_pointer_capture,
+                    (
+                        clickable::update_clickables,
+                        clickable::remove_click_components,
+                    ),
+                    clickable::add_click_components,
+                )
+                    .chain()
+                    .after(InputSystem),
+            )
+            .add_systems(
+                Update,
+                (
+                    mouse::mouse_button_input,
+                    mouse::scroll_events,
+                    city::click_city,
+                    click_off::kill_on_click_off.run_if(input_just_pressed(MouseButton::Left)),
+                    window::toggle_visibility_buttons,
+                    wagon::add_wagon_meshes.after(resource::spawn_node_wagons),
+                    (
+                        overlay::switch_overlay.run_if(not(any_with_component::<save::SavePrompt>)),
+                        overlay::update_overlay,
+                    )
+                        .chain()
+                        .run_if(in_state(states::AppState::InGame)),
+                    (
+                        save::quicksave
+                            .run_if(input_just_pressed(KeyCode::F5))
+                            .run_if(not(any_with_component::<save::SavePrompt>)),
+                        save::open_save_prompt.run_if(input_just_pressed(KeyCode::F6)),
+                        save::type_save_name,
+                    )
+                        .before(save::tick_save_manager)
+                        .run_if(in_state(states::AppState::InGame)),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (chunk::mark_stale_chunks, chunk::rebuild_map_chunks)
+                    .chain()
+                    .after(VisibilitySystems::CheckVisibility)
+                    .run_if(in_state(states::AppState::InGame)),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    ui::update_world_ui_positions,
+                    city::expose_cities.before(format_text::update_text_segments),
+                    format_text::update_text_segments,
+                    window::despawn_subwindows.before(click_off::despawn),
+                    window::update_parent_subwindows.before(click_off::despawn),
+                ),
+            );
+    }
+}
+
+#[cfg(feature = "debug")]
+fn add_debug_systems(app: &mut App, config: &GameConfig) {
+    if config.debug {
+        app.add_systems(
+            Update,
+            map::draw_debug.run_if(in_state(states::AppState::InGame)),
+        );
+    }
+
+    if config.debug_relations {
+        app.add_systems(
+            Update,
+            resource::debug_relations.run_if(in_state(states::AppState::InGame)),
+        );
+    }
+
+    if config.debug_roads {
+        app.add_systems(
+            Update,
+            road::debug_roads.run_if(in_state(states::AppState::InGame)),
+        );
+    }
+}
+
+#[cfg(feature = "render")]
+fn add_camera(mut commands: Commands) {
+    let ortho = OrthographicProjection {
+        scale: 1.0,
+        ..OrthographicProjection::default_2d()
+    };
+    commands.spawn((
+        Camera2d,
+        Projection::Orthographic(ortho),
+        Transform::from_xyz(500.0, 250.0, 0.0),
+    ));
+}
@@ -1,42 +1,25 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use bevy::{
-    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
-    input::{InputSystem, common_conditions::input_just_pressed},
-    prelude::*,
-};
-use bevy_common_assets::csv::CsvAssetPlugin;
-use bevy_prng::WyRand;
-use bevy_rand::plugin::EntropyPlugin;
+use bevy::prelude::*;
 use clap::Parser;
+use rome_game::{
+    GameConfig,
+    biome::BiomeRegistry,
+    city_names, export,
+    fingerprint::{self, GoldenCheck},
+    headless::HeadlessPlugin,
+    map_image::MapImages,
+    seed,
+    settings::{Settings, SettingsError},
+    worldgen,
+};
 
-mod biome;
-mod city;
-mod city_names;
-mod click_off;
-mod clickable;
-mod demographic;
-mod exposer_tags;
-mod format_text;
-mod history;
-mod keyboard;
-mod map;
-mod mouse;
-mod pointer_capture;
-mod resource;
-mod road;
-mod save;
-mod settings;
-mod shipment;
-mod states;
-mod ui;
-mod utils;
-mod wagon;
-mod window;
-
-#[derive(Parser, Debug, Resource)]
+/// The settings file used when `--config` isn't given, if it exists.
+const DEFAULT_SETTINGS_PATH: &str = "assets/settings.ron";
+
+#[derive(Parser, Debug)]
 #[command(about, long_about = None)]
-pub struct Args {
+struct Args {
     /// Display debug gizmos.
     #[arg(short, long)]
     debug: bool,
@@ -53,174 +36,203 @@ pub struct Args {
     #[arg(short, long)]
     performance: bool,
 
-    /// The seed to use for map generation.
+    /// The seed to use for map generation, either a number or a phrase such as `via-appia`.
+    /// A random seed is picked if this is left out.
     #[arg(short, long)]
-    seed: u64,
-}
+    seed: Option<String>,
 
-pub struct GamePlugin;
+    /// Load a saved game instead of generating a new map.
+    #[arg(short, long)]
+    load: Option<PathBuf>,
+
+    /// Read settings from this RON file instead of `assets/settings.ron`.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Override a single setting, for example `--set map_gen.city_num=20`.
+    /// Values are parsed as JSON, so ranges are written as `{"start":1,"end":3}`.
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, String)>,
+
+    /// Regenerate the worlds in `assets/fingerprints.ron` and check they haven't changed.
+    #[arg(long, conflicts_with = "record_fingerprints")]
+    check_fingerprints: bool,
+
+    /// Regenerate the worlds in `assets/fingerprints.ron` and record their fingerprints.
+    #[arg(long)]
+    record_fingerprints: bool,
+
+    /// Run the simulation without a window, then print statistics.
+    #[arg(long)]
+    headless: bool,
+
+    /// The number of simulated seconds to run for in headless mode.
+    // Builds without rendering are always headless, so only ask for --headless with rendering
+    #[arg(long, default_value_t = 600)]
+    #[cfg_attr(feature = "render", arg(requires = "headless"))]
+    ticks: u32,
+
+    /// Write headless statistics to a JSON file instead of printing them.
+    #[arg(long)]
+    #[cfg_attr(feature = "render", arg(requires = "headless"))]
+    stats: Option<PathBuf>,
+
+    /// Generate the map and write it to an SVG or PNG image or a GeoJSON file
+    /// instead of starting the game.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["load", "headless"])]
+    export: Option<PathBuf>,
+
+    /// Pixels per unit of map in exported PNGs.
+    #[arg(long, default_value_t = 1.0, requires = "export")]
+    export_scale: f32,
+}
 
-impl Plugin for GamePlugin {
-    fn build(&self, app: &mut App) {
-        let args = Args::parse();
+fn parse_override(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{}`", arg))
+}
 
-        if args.debug {
-            app.add_systems(
-                Update,
-                map::draw_debug.run_if(in_state(states::AppState::InGame)),
-            );
+impl Args {
+    /// Load the settings file, apply any overrides and check the result.
+    fn settings(&self) -> Result<Settings, SettingsError> {
+        let mut settings = match &self.config {
+            Some(path) => Settings::load(path)?,
+            None if Path::new(DEFAULT_SETTINGS_PATH).exists() => {
+                Settings::load(Path::new(DEFAULT_SETTINGS_PATH))?
+            }
+            None => Settings::default(),
+        };
+
+        for (key, value) in &self.overrides {
+            settings.set(key, value)?;
         }
 
-        if args.debug_relations {
-            app.add_systems(
-                Update,
-                resource::debug_relations.run_if(in_state(states::AppState::InGame)),
-            );
-        }
+        settings.validate()?;
+        Ok(settings)
+    }
 
-        if args.debug_roads {
-            app.add_systems(
-                Update,
-                road::debug_roads.run_if(in_state(states::AppState::InGame)),
-            );
+    fn config(&self, settings: Settings) -> GameConfig {
+        GameConfig {
+            seed: self.seed.as_deref().map(seed::parse_seed),
+            load: self.load.clone(),
+            debug: self.debug,
+            debug_relations: self.debug_relations,
+            debug_roads: self.debug_roads,
+            performance: self.performance,
+            settings,
         }
+    }
+}
 
-        if args.performance {
-            app.add_plugins((
-                LogDiagnosticsPlugin::default(),
-                FrameTimeDiagnosticsPlugin::default(),
-                bevy::diagnostic::SystemInformationDiagnosticsPlugin,
-            ));
+/// Check or record the golden world fingerprints, returning whether every check passed.
+fn golden_fingerprints(record: bool) -> Result<bool, String> {
+    let path = Path::new(fingerprint::GOLDEN_PATH);
+    let names = city_names::read_name_file(Path::new("assets/city-names.csv"))
+        .map_err(|err| format!("Failed to read city names: {}", err))?;
+    let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron"))
+        .map_err(|err| format!("Failed to read biomes: {}", err))?;
+    let mut golden = fingerprint::read_golden(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+    let mut passed = true;
+    for (seed, check) in fingerprint::check_golden(&golden, &names, &biomes) {
+        match check {
+            GoldenCheck::Matches => println!("seed {}: ok", seed),
+            GoldenCheck::Differs { expected, actual } => {
+                println!(
+                    "seed {}: fingerprint {:016x} doesn't match golden {:016x}",
+                    seed, actual, expected
+                );
+                passed &= record;
+                golden.insert(seed, Some(actual));
+            }
+            GoldenCheck::NotRecorded { actual } => {
+                println!("seed {}: fingerprint {:016x} not recorded", seed, actual);
+                passed &= record;
+                golden.insert(seed, Some(actual));
+            }
         }
+    }
 
-        app.insert_state(states::AppState::Loading)
-            .init_resource::<mouse::MousePos>()
-            .init_resource::<pointer_capture::IsPointerCaptured>()
-            .insert_resource(args)
-            .insert_resource(settings::MapGenSettings {
-                size: vec2(1000.0, 500.0),
-
-                sector_num: 16000,
-
-                lloyd_iters: 5,
-                generator_border: 20.0,
-                altitude_perlin_scale: 0.004,
-                water_cutoff: -0.6,
-
-                biome_seed_num: 120,
-
-                city_num: 10,
-                city_min_spacing: 100.0,
-                city_start_pop_range: 10..1000,
-                city_deadzone: 20.0,
-
-                nodes_per_city_range: 1..3,
-                node_city_max_dist: 100.0,
-                node_city_min_dist: 20.0,
-                node_min_spacing: 70.0,
-                node_deadzone: 10.0,
-            })
-            .insert_resource(settings::DisplaySettings { road_width: 4.0 })
-            .insert_resource(settings::GameplaySettings {
-                wagon_speed: 1.0,
-                node_wagon_spawn_time: 15.0,
-            })
-            .insert_resource(settings::SaveSettings {
-                save_directory: Path::new("./saves").into(),
-                save_interval: 3.0,
-            })
-            .add_systems(
-                OnEnter(states::AppState::InGame),
-                (
-                    map::create_map,
-                    map::generate_map,
-                    biome::generate_biomes,
-                    (
-                        map::add_map_mesh,
-                        city_names::set_unused_names,
-                        city::spawn_cities.after(city_names::set_unused_names),
-                    ),
-                    (
-                        city::add_city_meshes,
-                        resource::spawn_resource_nodes,
-                        history::setup_history_sim,
-                    ),
-                    (resource::add_node_meshes, history::run_history_sim),
-                    road::spawn_node_roads,
-                    road::add_road_meshes,
-                    save::spawn_save_manager,
-                )
-                    .chain(),
-            )
-            .add_systems(Startup, (add_camera, city_names::load_name_list))
-            .add_systems(
-                PreUpdate,
-                (
-                    mouse::update_mouse_pos,
-                    pointer_capture::update_pointer_capture,
-                    (
-                        clickable::update_clickables,
-                        clickable::remove_click_components,
-                    ),
-                    clickable::add_click_components,
-                )
-                    .chain()
-                    .after(InputSystem),
-            )
-            .add_systems(
-                Update,
-                (
-                    mouse::mouse_button_input,
-                    mouse::scroll_events,
-                    city::click_city,
-                    click_off::kill_on_click_off.run_if(input_just_pressed(MouseButton::Left)),
-                    demographic::update_demographics,
-                    demographic::update_city_pop.after(demographic::update_demographics),
-                    window::toggle_visibility_buttons,
-                    wagon::move_wagons,
-                    city::receive_city_shipments.after(wagon::move_wagons),
-                    resource::spawn_node_wagons,
-                    wagon::add_wagon_meshes.after(resource::spawn_node_wagons),
-                    save::tick_save_manager,
-                ),
-            )
-            .add_systems(
-                PostUpdate,
-                (
-                    ui::update_world_ui_positions,
-                    city::expose_cities.before(format_text::update_text_segments),
-                    format_text::update_text_segments,
-                    window::despawn_subwindows.before(click_off::despawn),
-                    window::update_parent_subwindows.before(click_off::despawn),
-                    click_off::despawn,
-                ),
-            )
-            .add_systems(
-                Update,
-                states::check_loaded.run_if(in_state(states::AppState::Loading)),
-            );
+    if record {
+        fingerprint::write_golden(path, &golden)
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        println!("Recorded fingerprints in {}", path.display());
     }
+
+    Ok(passed)
 }
 
-fn add_camera(mut commands: Commands) {
-    let ortho = OrthographicProjection {
-        scale: 1.0,
-        ..OrthographicProjection::default_2d()
-    };
-    commands.spawn((
-        Camera2d,
-        Projection::Orthographic(ortho),
-        Transform::from_xyz(500.0, 250.0, 0.0),
-    ));
+/// Generate the world described by the config without starting the game and export it.
+fn export_map(path: &Path, config: &GameConfig, scale: f32) -> Result<(), String> {
+    if scale <= 0.0 {
+        return Err("--export-scale must be positive".to_string());
+    }
+
+    let names = city_names::read_name_file(Path::new("assets/city-names.csv"))
+        .map_err(|err| format!("Failed to read city names: {}", err))?;
+    let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron"))
+        .map_err(|err| format!("Failed to read biomes: {}", err))?;
+    let map_gen = &config.settings.map_gen;
+    let images =
+        MapImages::load(map_gen).map_err(|err| format!("Failed to load map images: {}", err))?;
+
+    let seed = config.seed.unwrap_or_else(seed::random_seed);
+    println!("Generating map with seed {}", seed);
+    let world = worldgen::generate_world(map_gen, &biomes, &images, seed, &names);
+
+    export::write_map(path, &world, &config.settings, &biomes, scale)
+        .map_err(|err| format!("Failed to export {}: {}", path.display(), err))?;
+    println!("Exported map to {}", path.display());
+    Ok(())
 }
 
-fn main() {
-    App::new()
-        .add_plugins((
-            DefaultPlugins,
-            EntropyPlugin::<WyRand>::default(),
-            CsvAssetPlugin::<city_names::CityName>::new(&["csv"]),
-            GamePlugin,
-        ))
-        .run();
+fn main() -> AppExit {
+    let args = Args::parse();
+
+    if args.check_fingerprints || args.record_fingerprints {
+        return match golden_fingerprints(args.record_fingerprints) {
+            Ok(true) => AppExit::Success,
+            Ok(false) => AppExit::error(),
+            Err(err) => {
+                eprintln!("{}", err);
+                AppExit::error()
+            }
+        };
+    }
+    let settings = match args.settings() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("Failed to load settings: {}", err);
+            return AppExit::error();
+        }
+    };
+
+    let config = args.config(settings);
+
+    if let Some(path) = &args.export {
+        return match export_map(path, &config, args.export_scale) {
+            Ok(()) => AppExit::Success,
+            Err(err) => {
+                eprintln!("{}", err);
+                AppExit::error()
+            }
+        };
+    }
+
+    let mut app = App::new();
+    app.insert_resource(config);
+
+    if args.headless || cfg!(not(feature = "render")) {
+        app.add_plugins(HeadlessPlugin {
+            ticks: args.ticks,
+            stats: args.stats,
+        });
+    } else {
+        #[cfg(feature = "render")]
+        app.add_plugins((DefaultPlugins, rome_game::GamePlugin));
+    }
+
+    app.run()
 }
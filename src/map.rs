@@ -1,17 +1,28 @@
-use std::collections::HashMap;
+use std::time::Instant;
 
-use bevy::{
-    asset::RenderAssetUsages,
-    prelude::*,
-    render::mesh::{Indices, PrimitiveTopology},
-};
+use bevy::prelude::*;
+use bevy_common_assets::csv::LoadedCsv;
 use bevy_prng::WyRand;
 use bevy_rand::prelude::Entropy;
 use noiz::prelude::*;
 use rand::{Rng, SeedableRng};
 use voronoice::*;
 
-use crate::{Args, biome::Biome, settings::MapGenSettings, utils};
+#[cfg(feature = "render")]
+use crate::window::{EntryBuilder, WindowBuilder};
+use crate::{
+    GameConfig,
+    biome::{Biome, BiomeRegistry},
+    city_names::{self, CityName, NameListHandle},
+    map_image::MapImages,
+    seed,
+    settings::{GameplaySettings, MapGenSettings},
+    utils,
+    water::WaterBody,
+    worldgen::{self, WorldSector},
+};
+#[cfg(feature = "debug")]
+use crate::{mouse::MousePos, spatial::SpatialIndex};
 
 /// An entire game map, effectively a voronoi diagram.
 #[derive(Component)]
@@ -19,11 +30,14 @@ use crate::{Args, biome::Biome, settings::MapGenSettings, utils};
 pub struct Map {
     /// A list of all the sectors in the map.
     pub sectors: Vec<Entity>,
+
+    /// The seed this map was generated from.
+    pub seed: u64,
 }
 
 /// A single polygon in the voronoi diagram.
 #[derive(Debug, Component)]
-#[require(Mesh2d, Transform)]
+#[require(Transform)]
 pub struct Sector {
     /// The point that spawned this sector in the voronoi diagram.
     pub site: Vec2,
@@ -39,51 +53,68 @@ pub struct Sector {
 
     pub biome: Option<Biome>,
 
+    /// The body of water this sector is part of, or `None` for land.
+    pub water_body: Option<WaterBody>,
+
+    /// Whether this is a land sector next to water.
+    pub coastal: bool,
+
     /// The cost per unit of traversing this sector (for road pathfinding).
     pub cost: f32,
 
+    /// How many rivers flow through this sector, or 0 if it has no river.
+    pub river_flow: f32,
+
+    /// The sector this sector's river flows into.
+    pub downstream: Option<Entity>,
+
     /// This sector's neighbors.
     pub neighbors: Vec<Entity>,
 }
 
+/// Generate the whole world from the map's seed and spawn it.
 pub fn generate_map(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut Map, &mut Entropy<WyRand>)>,
+    mut query: Query<(Entity, &mut Map)>,
     settings: Res<MapGenSettings>,
+    gameplay_settings: Res<GameplaySettings>,
+    biomes: Res<BiomeRegistry>,
+    name_list: Res<NameListHandle>,
+    names: Res<Assets<LoadedCsv<CityName>>>,
 ) {
-    let (entity, mut map, mut rng) = query.single_mut().unwrap();
+    let (entity, mut map) = query.single_mut().unwrap();
 
     commands.entity(entity).despawn_related::<Children>();
 
+    let names = city_names::all_names(&name_list, &names);
+    let images = MapImages::load_or_default(&settings);
+    let world = worldgen::generate_world(&settings, &biomes, &images, map.seed, &names);
+    worldgen::spawn_world(&mut commands, entity, &mut map, &world, &gameplay_settings);
+}
+
+/// Generate the sectors of a map with heights but no biomes.
+/// Heights come from the heightmap if there is one, otherwise from noise.
+pub fn generate_sectors(
+    settings: &MapGenSettings,
+    images: &MapImages,
+    rng: &mut impl Rng,
+) -> Vec<WorldSector> {
     let mut perlin_noise =
         Noise::<(MixCellGradients<OrthoGrid, Smoothstep, QuickGradients>,)>::default();
     perlin_noise.set_seed(rng.random());
 
-    println!("Generating map with size: {}", settings.size);
+    info!("Generating map with size: {}", settings.size);
 
-    let mut sites: Vec<Vec2> = Vec::new();
-    for _ in 0..1000000 {
-        let new_site = Vec2 {
-            x: rng.random_range(0.0..(settings.size.x.round() + settings.generator_border * 2.0))
-                - settings.generator_border,
-            y: rng.random_range(0.0..(settings.size.y.round() + settings.generator_border * 2.0))
-                - settings.generator_border,
-        };
-        if sites.iter().any(|site| site.distance(new_site) < 1.0) {
-            continue;
-        }
-        sites.push(new_site);
-        if sites.len() >= settings.sector_num as usize {
-            break;
-        }
-    }
-    println!("Generated {} sites", sites.len());
+    let start = Instant::now();
+    let sites = sample_sites(settings, rng);
+    info!("Generated {} sites in {:.2?}", sites.len(), start.elapsed());
 
     let boundary = BoundingBox::new(
         vec2_to_point(&(settings.size / 2.0)),
         settings.size.x as f64,
         settings.size.y as f64,
     );
+    let start = Instant::now();
     let voronoi = VoronoiBuilder::default()
         .set_sites(sites.iter().map(vec2_to_point).collect())
         .set_bounding_box(boundary)
@@ -92,107 +123,121 @@ pub fn generate_map(
         .build()
         .unwrap();
 
-    println!("Sites after building mesh: {}", voronoi.sites().len());
-
-    // Maps VoronoiCell site indices to entity ID's
-    let mut site_to_entity: HashMap<usize, Entity> = HashMap::new();
-
-    // Maps VoronoiCell site indices to sectors
-    let mut site_to_sector: HashMap<usize, Sector> = HashMap::new();
-
-    for (site_index, cell) in voronoi.iter_cells().enumerate() {
-        let vertices: Vec<Vec2> = cell.iter_vertices().map(point_to_vec2).collect();
-
-        let site = point_to_vec2(cell.site_position());
-        let layer1: f32 = perlin_noise.sample(site * settings.altitude_perlin_scale);
-        let layer2: f32 = perlin_noise.sample(site * settings.altitude_perlin_scale * 4.0);
-        let height = layer1 + layer2 * 0.5;
-        let centroid = utils::centroid(&vertices);
-        let sector = Sector {
-            site,
-            centroid,
-            border: vertices,
-            height,
-            biome: None,
-            cost: 1.0,
-            neighbors: Vec::new(),
-        };
-
-        let sector_entity = commands.spawn(()).id();
-
-        map.sectors.push(sector_entity);
-        commands.entity(entity).add_child(sector_entity);
-
-        site_to_entity.insert(site_index, sector_entity);
-        site_to_sector.insert(site_index, sector);
-    }
-
-    for (site_index, cell) in voronoi.iter_cells().enumerate() {
-        let sector = site_to_sector.get_mut(&site_index).unwrap();
-        for neighbor_site_index in cell.iter_neighbors() {
-            sector
-                .neighbors
-                .push(*site_to_entity.get(&neighbor_site_index).unwrap());
-        }
+    info!(
+        "Sites after building mesh: {} in {:.2?}",
+        voronoi.sites().len(),
+        start.elapsed()
+    );
 
-        commands
-            .entity(*site_to_entity.get(&site_index).unwrap())
-            .insert(site_to_sector.remove(&site_index).unwrap());
-    }
+    // Sectors are stored in the same order as the voronoi cells, so cell
+    // neighbor indices can be used as sector indices directly.
+    voronoi
+        .iter_cells()
+        .map(|cell| {
+            let vertices: Vec<Vec2> = cell.iter_vertices().map(point_to_vec2).collect();
+
+            let site = point_to_vec2(cell.site_position());
+            let height = images.height_at(site, settings).unwrap_or_else(|| {
+                let layer1: f32 = perlin_noise.sample(site * settings.altitude_perlin_scale);
+                let layer2: f32 = perlin_noise.sample(site * settings.altitude_perlin_scale * 4.0);
+
+                // The shape mask is added before the water cutoff, so it decides where the
+                // coasts go. Its detail noise is offset so it doesn't line up with the main layers.
+                let detail: f32 = perlin_noise
+                    .sample((site + vec2(5000.0, 5000.0)) * settings.altitude_perlin_scale * 3.0);
+                let mask = settings.map_shape.mask(site / settings.size, detail);
+                layer1 + layer2 * 0.5 + mask * settings.shape_strength
+            });
+            let centroid = utils::centroid(&vertices);
+            WorldSector {
+                site,
+                centroid,
+                border: vertices,
+                height,
+                biome: None,
+                water_body: None,
+                coastal: false,
+                cost: 1.0,
+                river_flow: 0.0,
+                downstream: None,
+                neighbors: cell.iter_neighbors().collect(),
+            }
+        })
+        .collect()
 }
 
-pub fn add_map_mesh(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    map_query: Query<(Entity, &Map)>,
-    sector_query: Query<&Sector>,
-) {
-    // Data to be added to map mesh
-    let mut positions: Vec<Vec3> = Vec::new();
-    let mut triangles: Vec<u32> = Vec::new();
-    let mut colors: Vec<[f32; 4]> = Vec::new();
-
-    let (e_map, map) = map_query.single().unwrap();
+/// How many random sites [`sample_sites`] tries before giving up on reaching `sector_num`.
+const MAX_SITE_ATTEMPTS: u32 = 1_000_000;
+
+/// Scatter up to `sector_num` sites uniformly over the map and its generator border,
+/// rejecting any closer than `site_min_spacing` to a site already placed.
+/// Placed sites are kept in a grid of cells small enough to hold one site each,
+/// so a candidate only has to be checked against the few cells around it.
+///
+/// This is dart throwing rather than Bridson's Poisson-disk sampling. Bridson's algorithm grows
+/// outwards from one site until the area is full, so stopping it at `sector_num` would leave
+/// the sites bunched in one part of the map unless the spacing was derived from the count.
+fn sample_sites(settings: &MapGenSettings, rng: &mut impl Rng) -> Vec<Vec2> {
+    let spacing = settings.site_min_spacing;
+    let origin = Vec2::splat(-settings.generator_border);
+    let extent = settings.size.round() + settings.generator_border * 2.0;
+
+    // Cells are spacing / sqrt(2) wide, so two sites in one cell would be too close
+    let cell_size = spacing / std::f32::consts::SQRT_2;
+    let columns = (extent.x / cell_size).ceil() as usize + 1;
+    let rows = (extent.y / cell_size).ceil() as usize + 1;
+    let mut grid: Vec<Option<u32>> = vec![None; columns * rows];
+    let cell_of = |site: Vec2| -> (usize, usize) {
+        let cell = ((site - origin) / cell_size).floor();
+        (
+            (cell.x.max(0.0) as usize).min(columns - 1),
+            (cell.y.max(0.0) as usize).min(rows - 1),
+        )
+    };
 
-    for e_sector in &map.sectors {
-        let sector = sector_query.get(*e_sector).unwrap();
+    let mut sites: Vec<Vec2> = Vec::new();
+    for _ in 0..MAX_SITE_ATTEMPTS {
+        let new_site = Vec2 {
+            x: rng.random_range(0.0..extent.x) + origin.x,
+            y: rng.random_range(0.0..extent.y) + origin.y,
+        };
 
-        let index_offset = positions.len() as u32;
-        for i in 1..sector.border.len() - 1 {
-            triangles.push(index_offset as u32);
-            triangles.push(i as u32 + index_offset);
-            triangles.push(i as u32 + index_offset + 1);
+        // A site within the spacing is at most 2 cells away in either direction
+        let (column, row) = cell_of(new_site);
+        let too_close = (row.saturating_sub(2)..(row + 3).min(rows)).any(|row| {
+            (column.saturating_sub(2)..(column + 3).min(columns)).any(|column| {
+                grid[row * columns + column]
+                    .is_some_and(|i| sites[i as usize].distance(new_site) < spacing)
+            })
+        });
+        if too_close {
+            continue;
         }
-        let color = match sector.biome.unwrap() {
-            Biome::Plains => Color::srgb_u8(124, 252, 0),
-            Biome::Forest => Color::srgb_u8(34, 139, 34),
-            Biome::Desert => Color::srgb_u8(255, 255, 224),
-            Biome::Mountains => Color::srgb_u8(169, 169, 169),
-            Biome::Water => Color::srgb_u8(0, 0, 255),
-        };
-        for vertex in &sector.border {
-            positions.push(vertex.extend(0.0));
-            colors.push(color.to_linear().to_f32_array());
+
+        grid[row * columns + column] = Some(sites.len() as u32);
+        sites.push(new_site);
+        if sites.len() >= settings.sector_num as usize {
+            break;
         }
     }
 
-    let mesh = Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::RENDER_WORLD,
-    )
-    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
-    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
-    .with_inserted_indices(Indices::U32(triangles));
-    let mesh_handle = meshes.add(mesh);
-    let mesh_entity = commands
-        .spawn((
-            Mesh2d(mesh_handle),
-            MeshMaterial2d(materials.add(Color::WHITE)),
-            Transform::from_xyz(0.0, 0.0, 0.0),
-        ))
-        .id();
-    commands.entity(e_map).add_child(mesh_entity);
+    sites
+}
+
+/// Show the seed of the current map in the corner of the screen so it can be shared.
+#[cfg(feature = "render")]
+pub fn add_seed_label(mut commands: Commands, map_query: Query<&Map>) {
+    let Ok(map) = map_query.single() else {
+        return;
+    };
+
+    WindowBuilder::new()
+        .width(Val::Auto)
+        .height(Val::Auto)
+        .left(Val::Px(10.0))
+        .top(Val::Px(10.0))
+        .add_entry(EntryBuilder::text(&format!("Seed: {}", map.seed)))
+        .spawn(&mut commands);
 }
 
 fn vec2_to_point(v: &Vec2) -> Point {
@@ -209,7 +254,14 @@ fn point_to_vec2(p: &Point) -> Vec2 {
     }
 }
 
-pub fn draw_debug(mut gizmos: Gizmos, settings: Res<MapGenSettings>, sector_query: Query<&Sector>) {
+#[cfg(feature = "debug")]
+pub fn draw_debug(
+    mut gizmos: Gizmos,
+    settings: Res<MapGenSettings>,
+    sector_query: Query<&Sector>,
+    index: Res<SpatialIndex>,
+    mouse: Res<MousePos>,
+) {
     for sector in &sector_query {
         // gizmos.circle_2d(sector.site, 3.0, Color::WHITE);
         gizmos.circle_2d(sector.centroid, 3.0, Color::srgb(0.0, 1.0, 1.0));
@@ -224,17 +276,52 @@ pub fn draw_debug(mut gizmos: Gizmos, settings: Res<MapGenSettings>, sector_quer
     }
 
     gizmos.rect_2d(settings.size / 2.0, settings.size, Color::WHITE);
+
+    // Outline the sector under the mouse
+    let hovered = index
+        .sector_at(mouse.pos)
+        .and_then(|e_sector| sector_query.get(e_sector).ok());
+    if let Some(sector) = hovered {
+        gizmos.linestrip_2d(
+            sector.border.iter().chain(sector.border.first()).copied(),
+            Color::srgb(1.0, 1.0, 0.0),
+        );
+    }
 }
 
-pub fn create_map(mut commands: Commands, args: Res<Args>) {
-    let seed: u64 = args.seed;
+pub fn create_map(mut commands: Commands, config: Res<GameConfig>) {
+    let seed: u64 = config.seed.unwrap_or_else(seed::random_seed);
+    info!("Generating map with seed {}", seed);
 
     commands.spawn((
         Map {
             sectors: Vec::new(),
+            seed,
         },
         Entropy::<WyRand>::seed_from_u64(seed),
         Transform::IDENTITY,
         Visibility::Visible,
     ));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampled_sites_keep_their_spacing() {
+        let settings = MapGenSettings {
+            sector_num: 4000,
+            site_min_spacing: 4.0,
+            ..MapGenSettings::default()
+        };
+        let sites = sample_sites(&settings, &mut WyRand::seed_from_u64(42));
+        assert_eq!(sites.len(), settings.sector_num as usize);
+
+        for (i, site) in sites.iter().enumerate() {
+            for other in &sites[i + 1..] {
+                assert!(site.distance(*other) >= settings.site_min_spacing);
+            }
+        }
+    }
+}
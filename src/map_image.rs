@@ -0,0 +1,187 @@
+use std::{fmt::Display, path::Path};
+
+use bevy::prelude::*;
+use image::{ImageBuffer, ImageError, Luma, RgbImage};
+
+use crate::{
+    biome::{Biome, BiomeRegistry},
+    settings::MapGenSettings,
+};
+
+/// Hand-made images to take the map from instead of generating it,
+/// as set by `heightmap` and `biome_map` in [`MapGenSettings`].
+/// Images are stretched over the whole map, with the top of the image to the north.
+#[derive(Default)]
+pub struct MapImages {
+    pub heightmap: Option<ImageBuffer<Luma<f32>, Vec<f32>>>,
+    pub biome_map: Option<RgbImage>,
+}
+
+#[derive(Debug)]
+pub struct MapImageError {
+    pub path: Box<Path>,
+    pub error: ImageError,
+}
+
+impl Display for MapImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "can't read {}: {}", self.path.display(), self.error)
+    }
+}
+
+impl MapImages {
+    /// Read whichever images the settings ask for.
+    pub fn load(settings: &MapGenSettings) -> Result<MapImages, MapImageError> {
+        let open = |path: &Path| {
+            image::open(path).map_err(|error| MapImageError {
+                path: path.into(),
+                error,
+            })
+        };
+
+        Ok(MapImages {
+            heightmap: match &settings.heightmap {
+                Some(path) => Some(open(path.as_path())?.to_luma32f()),
+                None => None,
+            },
+            biome_map: match &settings.biome_map {
+                Some(path) => Some(open(path.as_path())?.to_rgb8()),
+                None => None,
+            },
+        })
+    }
+
+    /// Read whichever images the settings ask for, or generate the map from noise
+    /// if any of them can't be read.
+    pub fn load_or_default(settings: &MapGenSettings) -> MapImages {
+        MapImages::load(settings).unwrap_or_else(|err| {
+            error!(
+                "Failed to load map images: {}, generating from noise instead",
+                err
+            );
+            MapImages::default()
+        })
+    }
+
+    /// The height at a point on the map from the heightmap, blending between pixels,
+    /// or `None` if there's no heightmap.
+    /// Black is the start of `heightmap_range` and white is the end.
+    pub fn height_at(&self, point: Vec2, settings: &MapGenSettings) -> Option<f32> {
+        let heightmap = self.heightmap.as_ref()?;
+        let pixel = to_pixel(point, settings.size, heightmap.width(), heightmap.height());
+
+        let corner = pixel.floor();
+        let blend = pixel - corner;
+        let sample = |x: f32, y: f32| {
+            let x = (x as u32).min(heightmap.width() - 1);
+            let y = (y as u32).min(heightmap.height() - 1);
+            heightmap.get_pixel(x, y).0[0]
+        };
+        let top = sample(corner.x, corner.y).lerp(sample(corner.x + 1.0, corner.y), blend.x);
+        let bottom =
+            sample(corner.x, corner.y + 1.0).lerp(sample(corner.x + 1.0, corner.y + 1.0), blend.x);
+        let value = top.lerp(bottom, blend.y);
+
+        let range = &settings.heightmap_range;
+        Some(range.start + (range.end - range.start) * value)
+    }
+
+    /// The biome painted at a point on the map, matched to the biome with the closest color,
+    /// or `None` if there's no biome map.
+    pub fn biome_at(
+        &self,
+        point: Vec2,
+        settings: &MapGenSettings,
+        biomes: &BiomeRegistry,
+    ) -> Option<Biome> {
+        let biome_map = self.biome_map.as_ref()?;
+        let pixel = to_pixel(point, settings.size, biome_map.width(), biome_map.height()).round();
+        let [r, g, b] = biome_map.get_pixel(pixel.x as u32, pixel.y as u32).0;
+        Some(biomes.closest_color((r, g, b)))
+    }
+}
+
+/// The position of a point on the map in an image of this size, clamped to the image.
+fn to_pixel(point: Vec2, size: Vec2, width: u32, height: u32) -> Vec2 {
+    let uv = (point / size).clamp(Vec2::ZERO, Vec2::ONE);
+
+    // Image rows go down from the top, but the map's y axis goes up from the south
+    vec2(
+        uv.x * (width - 1) as f32,
+        (1.0 - uv.y) * (height - 1) as f32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> MapGenSettings {
+        MapGenSettings {
+            size: vec2(10.0, 10.0),
+            heightmap_range: -1.0..1.0,
+            ..MapGenSettings::default()
+        }
+    }
+
+    #[test]
+    fn heights_blend_between_pixels() {
+        // White in the top right, the north east of the map, and black everywhere else
+        let heightmap = ImageBuffer::from_vec(2, 2, vec![0.0, 1.0, 0.0, 0.0]).unwrap();
+        let images = MapImages {
+            heightmap: Some(heightmap),
+            biome_map: None,
+        };
+        let settings = settings();
+        let height_at = |x: f32, y: f32| images.height_at(vec2(x, y), &settings).unwrap();
+
+        assert_eq!(height_at(10.0, 10.0), 1.0);
+        assert_eq!(height_at(0.0, 10.0), -1.0);
+        assert_eq!(height_at(10.0, 0.0), -1.0);
+        assert_eq!(height_at(10.0, 5.0), 0.0);
+        assert_eq!(height_at(5.0, 5.0), -0.5);
+
+        // Points off the map take the height at the nearest edge
+        assert_eq!(height_at(20.0, 20.0), 1.0);
+    }
+
+    #[test]
+    fn biomes_come_from_the_nearest_pixel_and_closest_color() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let biome_map = RgbImage::from_vec(2, 1, vec![10, 10, 240, 120, 240, 10]).unwrap();
+        let images = MapImages {
+            heightmap: None,
+            biome_map: Some(biome_map),
+        };
+        let settings = settings();
+        let biome_at = |x: f32| images.biome_at(vec2(x, 5.0), &settings, &biomes);
+
+        let water = biomes.by_name("Water");
+        let plains = biomes.by_name("Plains");
+        assert_eq!(biome_at(0.0), water);
+        assert_eq!(biome_at(4.0), water);
+        assert_eq!(biome_at(6.0), plains);
+        assert_eq!(biome_at(10.0), plains);
+    }
+
+    #[test]
+    fn maps_without_images_use_noise() {
+        let images = MapImages::default();
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        assert_eq!(images.height_at(Vec2::ZERO, &settings()), None);
+        assert_eq!(images.biome_at(Vec2::ZERO, &settings(), &biomes), None);
+    }
+
+    #[test]
+    fn missing_images_fall_back_to_noise() {
+        let settings = MapGenSettings {
+            heightmap: Some("assets/no-such-heightmap.png".into()),
+            ..MapGenSettings::default()
+        };
+        let err = MapImages::load(&settings).err().unwrap();
+        assert!(err.to_string().contains("no-such-heightmap.png"));
+
+        let images = MapImages::load_or_default(&settings);
+        assert!(images.heightmap.is_none() && images.biome_map.is_none());
+    }
+}
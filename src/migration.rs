@@ -0,0 +1,377 @@
+use bevy::math::Vec2;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::{province, save::SaveError, settings::MapGenSettings, water};
+
+/// The save format version written by this build.
+/// Bump this and add a step to [`MIGRATIONS`] whenever the saved form of the game changes.
+pub const FORMAT_VERSION: u32 = 7;
+
+/// A save file that has been parsed as JSON but not yet deserialized.
+pub struct SaveDocument {
+    pub header: Value,
+
+    /// The game state, or `None` if only the header has been read.
+    pub world: Option<Value>,
+}
+
+impl SaveDocument {
+    /// The format version this document was written with.
+    pub fn version(&self) -> Result<u32, SaveError> {
+        self.header
+            .get("version")
+            .and_then(Value::as_u64)
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or(SaveError::BadHeader)
+    }
+}
+
+/// A function that upgrades a document by exactly one format version.
+type Migration = fn(&mut SaveDocument) -> Result<(), SaveError>;
+
+/// Every migration in order.
+/// `MIGRATIONS[i]` upgrades a document from version `i + 1` to version `i + 2`.
+const MIGRATIONS: [Migration; (FORMAT_VERSION - 1) as usize] = [
+    add_save_metadata,
+    add_rivers,
+    add_biome_mode,
+    add_water_bodies,
+    add_provinces,
+    add_received_shipments,
+];
+
+/// Upgrade a document to [`FORMAT_VERSION`] one version at a time.
+pub fn migrate(doc: &mut SaveDocument) -> Result<(), SaveError> {
+    let version = doc.version()?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(SaveError::UnsupportedVersion(version));
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        migration(doc)?;
+        doc.header["version"] = Value::from(i as u32 + 2);
+    }
+
+    Ok(())
+}
+
+/// Version 2 added metadata to the header and the in-game date to the history.
+/// Version 1 saves didn't record when they were made, so they get placeholder values.
+fn add_save_metadata(doc: &mut SaveDocument) -> Result<(), SaveError> {
+    let header = doc
+        .header
+        .as_object_mut()
+        .ok_or(SaveError::MigrationFailed(1))?;
+    header.insert("name".into(), json!("Unnamed"));
+    header.insert("kind".into(), json!("Manual"));
+    header.insert("timestamp".into(), json!("1970-01-01T00:00:00Z"));
+    header.insert("date".into(), json!("-0300-01-01"));
+
+    if let Some(world) = &mut doc.world {
+        let world = world.as_object_mut().ok_or(SaveError::MigrationFailed(1))?;
+        let events = world.remove("history").unwrap_or(json!([]));
+        world.insert(
+            "history".into(),
+            json!({ "events": events, "date": "-0300-01-01", "day_progress": 0.0 }),
+        );
+    }
+
+    Ok(())
+}
+
+/// Version 3 added rivers to sectors and river settings to map generation.
+/// Older maps have no rivers, so they keep generating none if their settings are reused.
+fn add_rivers(doc: &mut SaveDocument) -> Result<(), SaveError> {
+    let map_gen = doc
+        .header
+        .get_mut("map_gen")
+        .and_then(Value::as_object_mut)
+        .ok_or(SaveError::MigrationFailed(2))?;
+    map_gen.insert("river_num".into(), json!(0));
+
+    if let Some(world) = &mut doc.world {
+        let sectors = world
+            .get_mut("sectors")
+            .and_then(Value::as_array_mut)
+            .ok_or(SaveError::MigrationFailed(2))?;
+        for sector in sectors {
+            let sector = sector
+                .as_object_mut()
+                .ok_or(SaveError::MigrationFailed(2))?;
+            sector.insert("river_flow".into(), json!(0.0));
+            sector.insert("downstream".into(), Value::Null);
+        }
+    }
+
+    Ok(())
+}
+
+/// Version 4 added a choice of biome generation. Older maps were all flood filled.
+fn add_biome_mode(doc: &mut SaveDocument) -> Result<(), SaveError> {
+    let map_gen = doc
+        .header
+        .get_mut("map_gen")
+        .and_then(Value::as_object_mut)
+        .ok_or(SaveError::MigrationFailed(3))?;
+    map_gen.insert("biome_mode".into(), json!("FloodFill"));
+
+    Ok(())
+}
+
+/// Version 5 split water into oceans, inland seas and lakes and marked coastal land.
+/// Older saves only had the built-in biomes, so the water is every sector named `Water`,
+/// labelled the same way new maps are.
+fn add_water_bodies(doc: &mut SaveDocument) -> Result<(), SaveError> {
+    let map_gen = doc
+        .header
+        .get_mut("map_gen")
+        .and_then(Value::as_object_mut)
+        .ok_or(SaveError::MigrationFailed(4))?;
+    let inland_sea_min_size = MapGenSettings::default().inland_sea_min_size;
+    map_gen.insert("inland_sea_min_size".into(), json!(inland_sea_min_size));
+    let size: Vec2 = map_gen
+        .get("size")
+        .and_then(|size| serde_json::from_value(size.clone()).ok())
+        .ok_or(SaveError::MigrationFailed(4))?;
+
+    let Some(world) = &mut doc.world else {
+        return Ok(());
+    };
+    let sectors = world
+        .get_mut("sectors")
+        .and_then(Value::as_array_mut)
+        .ok_or(SaveError::MigrationFailed(4))?;
+
+    let mut is_water = Vec::new();
+    let mut on_edge = Vec::new();
+    let mut neighbors = Vec::new();
+    for sector in sectors.iter() {
+        is_water.push(sector.get("biome").and_then(Value::as_str) == Some("Water"));
+        let border: Vec<Vec2> = sector
+            .get("border")
+            .and_then(|border| serde_json::from_value(border.clone()).ok())
+            .ok_or(SaveError::MigrationFailed(4))?;
+        on_edge.push(water::touches_edge(&border, size));
+        let sector_neighbors: Vec<usize> = sector
+            .get("neighbors")
+            .and_then(|neighbors| serde_json::from_value(neighbors.clone()).ok())
+            .ok_or(SaveError::MigrationFailed(4))?;
+        if sector_neighbors.iter().any(|i| *i >= sectors.len()) {
+            return Err(SaveError::MigrationFailed(4));
+        }
+        neighbors.push(sector_neighbors);
+    }
+
+    let neighbors: Vec<&[usize]> = neighbors.iter().map(Vec::as_slice).collect();
+    let (water_bodies, coastal) = water::label_water(
+        &is_water,
+        &on_edge,
+        &neighbors,
+        inland_sea_min_size as usize,
+    );
+
+    for ((sector, water_body), coastal) in sectors.iter_mut().zip(water_bodies).zip(coastal) {
+        let sector = sector
+            .as_object_mut()
+            .ok_or(SaveError::MigrationFailed(4))?;
+        sector.insert("water_body".into(), json!(water_body));
+        sector.insert("coastal".into(), json!(coastal));
+    }
+
+    Ok(())
+}
+
+/// Version 6 added provinces. Older maps get one province around each city,
+/// grown the same way new maps with the default settings are.
+fn add_provinces(doc: &mut SaveDocument) -> Result<(), SaveError> {
+    let Some(world) = &mut doc.world else {
+        return Ok(());
+    };
+    let sectors = world
+        .get("sectors")
+        .and_then(Value::as_array)
+        .ok_or(SaveError::MigrationFailed(5))?;
+    let mut is_land = Vec::new();
+    let mut centroids: Vec<Vec2> = Vec::new();
+    let mut costs: Vec<f32> = Vec::new();
+    let mut neighbors = Vec::new();
+    for sector in sectors {
+        is_land.push(sector.get("water_body").is_some_and(Value::is_null));
+        centroids.push(read_field(sector, "centroid", 5)?);
+        costs.push(read_field(sector, "cost", 5)?);
+        let sector_neighbors: Vec<usize> = read_field(sector, "neighbors", 5)?;
+        if sector_neighbors.iter().any(|i| *i >= sectors.len()) {
+            return Err(SaveError::MigrationFailed(5));
+        }
+        neighbors.push(sector_neighbors);
+    }
+
+    let cities = world
+        .get("cities")
+        .and_then(Value::as_array)
+        .ok_or(SaveError::MigrationFailed(5))?;
+    let mut names = Vec::new();
+    let mut seeds = Vec::new();
+    for city in cities {
+        names.push(read_field::<String>(city, "name", 5)?);
+        let sector: usize = read_field(city, "sector", 5)?;
+        if sector >= sectors.len() {
+            return Err(SaveError::MigrationFailed(5));
+        }
+        seeds.push(sector);
+    }
+
+    let neighbors: Vec<&[usize]> = neighbors.iter().map(Vec::as_slice).collect();
+    let owners = province::grow_provinces(&seeds, &is_land, &centroids, &costs, &neighbors);
+    let mut province_sectors: Vec<Vec<usize>> = vec![Vec::new(); seeds.len()];
+    for (i, owner) in owners.into_iter().enumerate() {
+        if let Some(owner) = owner {
+            province_sectors[owner].push(i);
+        }
+    }
+
+    let provinces: Vec<Value> = names
+        .into_iter()
+        .zip(province_sectors)
+        .enumerate()
+        .filter(|(_, (_, sectors))| !sectors.is_empty())
+        .map(|(i, (name, sectors))| json!({ "name": name, "capital": i, "sectors": sectors }))
+        .collect();
+    world
+        .as_object_mut()
+        .ok_or(SaveError::MigrationFailed(5))?
+        .insert("provinces".into(), Value::Array(provinces));
+
+    Ok(())
+}
+
+/// Version 7 added the totals of shipments each city has received.
+/// Older saves didn't keep them, so every city starts counting again from nothing.
+fn add_received_shipments(doc: &mut SaveDocument) -> Result<(), SaveError> {
+    let Some(world) = &mut doc.world else {
+        return Ok(());
+    };
+    let cities = world
+        .get_mut("cities")
+        .and_then(Value::as_array_mut)
+        .ok_or(SaveError::MigrationFailed(6))?;
+    for city in cities {
+        let city = city.as_object_mut().ok_or(SaveError::MigrationFailed(6))?;
+        city.insert("received".into(), json!([]));
+    }
+
+    Ok(())
+}
+
+/// Read one field of a JSON object as a value, failing the migration from `version` if it's
+/// missing or the wrong shape.
+fn read_field<T: DeserializeOwned>(value: &Value, key: &str, version: u32) -> Result<T, SaveError> {
+    value
+        .get(key)
+        .and_then(|field| serde_json::from_value(field.clone()).ok())
+        .ok_or(SaveError::MigrationFailed(version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::{SaveData, SaveHeader};
+
+    /// A tiny version 1 save: a strip of ocean, then two plains sectors with a city on the first.
+    fn version_1_save() -> SaveDocument {
+        let sector = |x: f32, biome: &str, neighbors: &[usize]| {
+            json!({
+                "site": [x + 0.5, 0.5],
+                "border": [[x, 0.0], [x + 1.0, 0.0], [x + 1.0, 1.0], [x, 1.0]],
+                "centroid": [x + 0.5, 0.5],
+                "height": if biome == "Water" { -1.0 } else { 0.0 },
+                "biome": biome,
+                "cost": 1.0,
+                "neighbors": neighbors,
+            })
+        };
+
+        SaveDocument {
+            header: json!({
+                "version": 1,
+                "seed": 7,
+                "map_gen": { "size": [3.0, 1.0] },
+            }),
+            world: Some(json!({
+                "sectors": [
+                    sector(0.0, "Water", &[1]),
+                    sector(1.0, "Plains", &[0, 2]),
+                    sector(2.0, "Plains", &[1]),
+                ],
+                "cities": [{
+                    "name": "Roma",
+                    "position": [1.5, 0.5],
+                    "sector": 1,
+                    "population": 100,
+                    "resource_nodes": [],
+                    "demographics": [{ "job": "Unemployed", "population": 100 }],
+                    "neighbors": null,
+                }],
+                "nodes": [],
+                "roads": [],
+                "wagons": [],
+                "history": [],
+            })),
+        }
+    }
+
+    #[test]
+    fn version_1_saves_migrate_to_the_current_version() {
+        let mut doc = version_1_save();
+        migrate(&mut doc).unwrap();
+
+        let header: SaveHeader = serde_json::from_value(doc.header).unwrap();
+        let data: SaveData = serde_json::from_value(doc.world.unwrap()).unwrap();
+        assert_eq!(header.version, FORMAT_VERSION);
+        assert_eq!(header.map_gen.river_num, 0);
+
+        assert!(data.sectors[0].water_body.is_some());
+        assert!(data.sectors[1].coastal);
+        assert!(
+            data.sectors
+                .iter()
+                .all(|sector| sector.downstream.is_none())
+        );
+        assert!(data.cities[0].received.is_empty());
+
+        assert_eq!(data.provinces.len(), 1);
+        assert_eq!(data.provinces[0].name, "Roma");
+        assert_eq!(data.provinces[0].capital, Some(0));
+        assert_eq!(data.provinces[0].sectors, vec![1, 2]);
+    }
+
+    #[test]
+    fn saves_from_every_version_migrate() {
+        for version in 1..=FORMAT_VERSION {
+            // Bring the save up to this version, as if it had been written by then
+            let mut doc = version_1_save();
+            for (i, migration) in MIGRATIONS.iter().enumerate().take(version as usize - 1) {
+                migration(&mut doc).unwrap();
+                doc.header["version"] = json!(i as u32 + 2);
+            }
+
+            migrate(&mut doc).unwrap();
+            assert_eq!(doc.version().unwrap(), FORMAT_VERSION);
+            serde_json::from_value::<SaveHeader>(doc.header).unwrap();
+            serde_json::from_value::<SaveData>(doc.world.unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        for version in [0, FORMAT_VERSION + 1] {
+            let mut doc = version_1_save();
+            doc.header["version"] = json!(version);
+            assert!(matches!(
+                migrate(&mut doc),
+                Err(SaveError::UnsupportedVersion(_))
+            ));
+        }
+    }
+}
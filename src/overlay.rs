@@ -0,0 +1,329 @@
+use bevy::{ecs::system::SystemParam, platform::collections::HashMap, prelude::*};
+
+use crate::{
+    biome::BiomeRegistry,
+    chunk::MapChunk,
+    city::City,
+    click_off::Despawning,
+    map::{Map, Sector},
+    province::Province,
+    wagon::SectorTraffic,
+    window::{EntryBuilder, WindowBuilder},
+};
+
+/// How often the traffic overlay catches up with new wagons, in seconds.
+const TRAFFIC_REFRESH_SECS: f32 = 2.0;
+
+/// The color water is drawn with in overlays that only describe land, as sRGB.
+const WATER_COLOR: (u8, u8, u8) = (90, 100, 120);
+
+/// The color of land no city owns in [`MapOverlay::Owner`], as sRGB.
+const UNOWNED_COLOR: (u8, u8, u8) = (150, 150, 150);
+
+/// The number of labelled steps in the legend of an overlay with a color scale.
+const LEGEND_STEPS: usize = 5;
+
+/// A way of recoloring the map to show one property of every sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapOverlay {
+    /// The normal map.
+    #[default]
+    None,
+
+    /// [`Sector::height`], water included.
+    Height,
+
+    /// [`Sector::cost`] of crossing land.
+    Cost,
+
+    /// The plain biome colors, without any shading.
+    Biome,
+
+    /// The capital of the province each sector is in.
+    Owner,
+
+    /// How many wagons have passed through each sector, see [`SectorTraffic`].
+    Traffic,
+}
+
+impl MapOverlay {
+    /// Every overlay, in the order of their number keys from 0.
+    const ALL: [MapOverlay; 6] = [
+        Self::None,
+        Self::Height,
+        Self::Cost,
+        Self::Biome,
+        Self::Owner,
+        Self::Traffic,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Height => "Height",
+            Self::Cost => "Traversal cost",
+            Self::Biome => "Biome",
+            Self::Owner => "Owning city",
+            Self::Traffic => "Wagon traffic",
+        }
+    }
+}
+
+/// The overlay currently drawn over the map.
+#[derive(Resource, Debug, Default)]
+pub struct ActiveOverlay(pub MapOverlay);
+
+/// The color of every sector under the active overlay, empty when there is no overlay.
+/// Sectors with a color here are drawn flat in it instead of with the map palette.
+#[derive(Resource, Debug, Default)]
+pub struct OverlayColors(pub HashMap<Entity, LinearRgba>);
+
+/// Marker component for the legend window of the active overlay.
+#[derive(Component)]
+pub struct OverlayLegend;
+
+/// Everything [`update_overlay`] reads from the game.
+#[derive(SystemParam)]
+pub struct OverlaySources<'w, 's> {
+    time: Res<'w, Time>,
+    map: Query<'w, 's, &'static Map>,
+    sectors: Query<'w, 's, &'static Sector>,
+    provinces: Query<'w, 's, &'static Province>,
+    cities: Query<'w, 's, &'static City>,
+    traffic: Res<'w, SectorTraffic>,
+    biomes: Res<'w, BiomeRegistry>,
+}
+
+/// Switch overlays with the number keys, or cycle through them with tab.
+/// Pressing the key of the active overlay turns it off.
+pub fn switch_overlay(keys: Res<ButtonInput<KeyCode>>, mut active: ResMut<ActiveOverlay>) {
+    const DIGITS: [KeyCode; 6] = [
+        KeyCode::Digit0,
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+    ];
+
+    let current = MapOverlay::ALL
+        .iter()
+        .position(|overlay| *overlay == active.0)
+        .unwrap();
+    let next = if keys.just_pressed(KeyCode::Tab) {
+        Some((current + 1) % MapOverlay::ALL.len())
+    } else {
+        DIGITS
+            .iter()
+            .position(|key| keys.just_pressed(*key))
+            .map(|pressed| if pressed == current { 0 } else { pressed })
+    };
+
+    if let Some(next) = next {
+        active.0 = MapOverlay::ALL[next];
+    }
+}
+
+/// Recolor the map and replace the legend whenever the overlay changes,
+/// and every few seconds for overlays of values that change during the game.
+pub fn update_overlay(
+    mut commands: Commands,
+    mut since_refresh: Local<f32>,
+    active: Res<ActiveOverlay>,
+    mut colors: ResMut<OverlayColors>,
+    sources: OverlaySources,
+    legend_query: Query<Entity, With<OverlayLegend>>,
+    mut chunk_query: Query<&mut MapChunk>,
+) {
+    *since_refresh += sources.time.delta_secs();
+    let refresh = active.0 == MapOverlay::Traffic && *since_refresh >= TRAFFIC_REFRESH_SECS;
+    if !active.is_changed() && !refresh {
+        return;
+    }
+    *since_refresh = 0.0;
+
+    let Ok(map) = sources.map.single() else {
+        return;
+    };
+    let sectors: Vec<(Entity, &Sector)> = map
+        .sectors
+        .iter()
+        .map(|e_sector| (*e_sector, sources.sectors.get(*e_sector).unwrap()))
+        .collect();
+    let is_water = |sector: &Sector| sector.water_body.is_some();
+    let water_color = srgb(WATER_COLOR);
+
+    let mut legend: Vec<EntryBuilder> = Vec::new();
+    colors.0 = match active.0 {
+        MapOverlay::None => HashMap::new(),
+        MapOverlay::Height => scale_colors(
+            sectors
+                .iter()
+                .map(|(e_sector, sector)| (*e_sector, Some(sector.height))),
+            water_color,
+            |value| format!("{:.2}", value),
+            &mut legend,
+        ),
+        MapOverlay::Cost => scale_colors(
+            sectors
+                .iter()
+                .map(|(e_sector, sector)| (*e_sector, (!is_water(sector)).then_some(sector.cost))),
+            water_color,
+            |value| format!("{:.1}", value),
+            &mut legend,
+        ),
+        MapOverlay::Traffic => scale_colors(
+            sectors.iter().map(|(e_sector, sector)| {
+                let wagons = sources.traffic.0.get(e_sector).copied().unwrap_or(0);
+                (*e_sector, (!is_water(sector)).then_some(wagons as f32))
+            }),
+            water_color,
+            |value| format!("{:.0} wagons", value),
+            &mut legend,
+        ),
+        MapOverlay::Biome => {
+            let mut shown: Vec<(String, Color)> = Vec::new();
+            let biome_colors = sectors
+                .iter()
+                .map(|(e_sector, sector)| {
+                    let def = sources.biomes.get(sector.biome.unwrap());
+                    let map_color = def.map_color(sector.water_body);
+                    let color = srgb(map_color);
+
+                    // Lakes and inland seas can have a color of their own
+                    let name = if map_color == def.color {
+                        def.name.clone()
+                    } else {
+                        format!("{} (inland)", def.name)
+                    };
+                    if !shown.iter().any(|(shown_name, _)| *shown_name == name) {
+                        shown.push((name, color));
+                    }
+                    (*e_sector, color.to_linear())
+                })
+                .collect();
+            legend.extend(
+                shown
+                    .iter()
+                    .map(|(name, color)| EntryBuilder::swatch(*color, name)),
+            );
+            biome_colors
+        }
+        MapOverlay::Owner => {
+            let mut owners: HashMap<Entity, Color> = HashMap::new();
+            for (i, province) in sources.provinces.iter().enumerate() {
+                let Some(e_capital) = province.capital else {
+                    continue;
+                };
+                let color = owner_color(i);
+                if let Ok(city) = sources.cities.get(e_capital) {
+                    legend.push(EntryBuilder::swatch(color, &city.name));
+                }
+                for e_sector in &province.sectors {
+                    owners.insert(*e_sector, color);
+                }
+            }
+            let unowned = srgb(UNOWNED_COLOR);
+            legend.push(EntryBuilder::swatch(unowned, "Unowned"));
+
+            sectors
+                .iter()
+                .map(|(e_sector, sector)| {
+                    let color = if is_water(sector) {
+                        water_color
+                    } else {
+                        owners.get(e_sector).copied().unwrap_or(unowned)
+                    };
+                    (*e_sector, color.to_linear())
+                })
+                .collect()
+        }
+    };
+
+    for mut chunk in &mut chunk_query {
+        chunk.stale = true;
+    }
+
+    for e_legend in &legend_query {
+        commands.entity(e_legend).insert(Despawning);
+    }
+    if active.0 == MapOverlay::None {
+        return;
+    }
+    let mut window = WindowBuilder::new()
+        .width(Val::Auto)
+        .height(Val::Auto)
+        .left(Val::Px(10.0))
+        .top(Val::Px(60.0))
+        .add_entry(EntryBuilder::text(active.0.name()).centered());
+    for entry in legend {
+        window = window.add_entry(entry);
+    }
+    let e_window = window
+        .add_entry(EntryBuilder::text("Keys 1-5 switch overlays, 0 hides them"))
+        .spawn(&mut commands);
+    commands.entity(e_window).insert(OverlayLegend);
+}
+
+/// Color sectors on a scale from their lowest value to their highest, adding the scale to
+/// the legend. Sectors without a value are drawn in `none_color`.
+fn scale_colors(
+    values: impl Iterator<Item = (Entity, Option<f32>)>,
+    none_color: Color,
+    label: impl Fn(f32) -> String,
+    legend: &mut Vec<EntryBuilder>,
+) -> HashMap<Entity, LinearRgba> {
+    let values: Vec<(Entity, Option<f32>)> = values.collect();
+    let (lowest, highest) = values.iter().filter_map(|(_, value)| *value).fold(
+        (f32::INFINITY, f32::NEG_INFINITY),
+        |(lowest, highest), value| (lowest.min(value), highest.max(value)),
+    );
+    if lowest > highest {
+        legend.push(EntryBuilder::text("No data"));
+        return values
+            .into_iter()
+            .map(|(e_sector, _)| (e_sector, none_color.to_linear()))
+            .collect();
+    }
+    let range = (highest - lowest).max(f32::EPSILON);
+
+    // Highest first, as scales usually read from the top down
+    for step in (0..LEGEND_STEPS).rev() {
+        let fraction = step as f32 / (LEGEND_STEPS - 1) as f32;
+        legend.push(EntryBuilder::swatch(
+            color_scale(fraction),
+            &label(lowest + range * fraction),
+        ));
+    }
+
+    values
+        .into_iter()
+        .map(|(e_sector, value)| {
+            let color = match value {
+                Some(value) => color_scale((value - lowest) / range),
+                None => none_color,
+            };
+            (e_sector, color.to_linear())
+        })
+        .collect()
+}
+
+/// A color from dark purple at 0 through teal to yellow at 1, which stays readable
+/// to colorblind viewers and in grayscale.
+fn color_scale(fraction: f32) -> Color {
+    const STOPS: [(u8, u8, u8); 3] = [(68, 1, 84), (33, 145, 140), (253, 231, 37)];
+
+    let scaled = fraction.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (scaled as usize).min(STOPS.len() - 2);
+    srgb(STOPS[i]).mix(&srgb(STOPS[i + 1]), scaled - i as f32)
+}
+
+/// A distinct color for the `i`th owner, stepping around the hue wheel by the golden angle
+/// so neighboring indices never look alike.
+fn owner_color(i: usize) -> Color {
+    Color::hsl((i as f32 * 137.5) % 360.0, 0.65, 0.55)
+}
+
+fn srgb((r, g, b): (u8, u8, u8)) -> Color {
+    Color::srgb_u8(r, g, b)
+}
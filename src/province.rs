@@ -0,0 +1,446 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::{
+    asset::RenderAssetUsages,
+    platform::collections::HashMap,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    biome::BiomeRegistry,
+    city_names::take_name,
+    settings::MapGenSettings,
+    worldgen::{WorldCity, WorldSector},
+};
+#[cfg(feature = "render")]
+use crate::{
+    map::{Map, Sector},
+    settings::DisplaySettings,
+};
+
+/// The color province borders are drawn with, as sRGB.
+pub const PROVINCE_BORDER_COLOR: (u8, u8, u8) = (70, 45, 40);
+
+/// A named group of neighboring land sectors, the unit land is governed and taxed by.
+#[derive(Component, Debug)]
+pub struct Province {
+    pub name: String,
+
+    /// The city this province is governed from, or `None` if it has no cities.
+    pub capital: Option<Entity>,
+
+    /// The sectors making up this province.
+    pub sectors: Vec<Entity>,
+}
+
+/// Where provinces grow from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ProvinceSeeds {
+    /// One province around every city, named after it and governed from it.
+    #[default]
+    Cities,
+
+    /// `province_num` provinces around random land sectors, with names of their own.
+    /// Each is governed from its most populous city.
+    Random,
+}
+
+/// A generated province, see [`Province`].
+#[derive(Debug, Clone)]
+pub struct WorldProvince {
+    pub name: String,
+    pub capital: Option<usize>,
+
+    /// The province's sectors in increasing order.
+    pub sectors: Vec<usize>,
+}
+
+/// Split the land into provinces grown from seed sectors, chosen as set by `province_seeds`.
+/// Names for random seeds are drawn from `names`, skipping any a city already has.
+pub fn generate_provinces(
+    sectors: &[WorldSector],
+    cities: &[WorldCity],
+    names: &[String],
+    settings: &MapGenSettings,
+    biomes: &BiomeRegistry,
+    rng: &mut impl Rng,
+) -> Vec<WorldProvince> {
+    let is_land: Vec<bool> = sectors
+        .iter()
+        .map(|sector| !biomes.is_water(sector.biome))
+        .collect();
+
+    let mut provinces: Vec<(usize, WorldProvince)> = match settings.province_seeds {
+        ProvinceSeeds::Cities => cities
+            .iter()
+            .enumerate()
+            .map(|(i, city)| {
+                let province = WorldProvince {
+                    name: city.name.clone(),
+                    capital: Some(i),
+                    sectors: Vec::new(),
+                };
+                (city.sector, province)
+            })
+            .collect(),
+        ProvinceSeeds::Random => {
+            let mut seeds: Vec<usize> = Vec::new();
+            for _ in 0..1000 {
+                let sector = rng.random_range(0..sectors.len());
+                if !is_land[sector] || seeds.contains(&sector) {
+                    continue;
+                }
+
+                seeds.push(sector);
+                if seeds.len() >= settings.province_num as usize {
+                    break;
+                }
+            }
+
+            let mut unused_names: Vec<String> = names
+                .iter()
+                .filter(|name| !cities.iter().any(|city| city.name == **name))
+                .cloned()
+                .collect();
+            seeds
+                .into_iter()
+                .enumerate()
+                .map(|(i, sector)| {
+                    let name = if unused_names.is_empty() {
+                        format!("Province {}", i + 1)
+                    } else {
+                        take_name(&mut unused_names, rng)
+                    };
+                    let province = WorldProvince {
+                        name,
+                        capital: None,
+                        sectors: Vec::new(),
+                    };
+                    (sector, province)
+                })
+                .collect()
+        }
+    };
+
+    let seeds: Vec<usize> = provinces.iter().map(|(sector, _)| *sector).collect();
+    let centroids: Vec<Vec2> = sectors.iter().map(|sector| sector.centroid).collect();
+    let costs: Vec<f32> = sectors.iter().map(|sector| sector.cost).collect();
+    let neighbors: Vec<&[usize]> = sectors
+        .iter()
+        .map(|sector| sector.neighbors.as_slice())
+        .collect();
+    let owners = grow_provinces(&seeds, &is_land, &centroids, &costs, &neighbors);
+    for (i, owner) in owners.into_iter().enumerate() {
+        if let Some(owner) = owner {
+            provinces[owner].1.sectors.push(i);
+        }
+    }
+
+    let mut provinces: Vec<WorldProvince> = provinces
+        .into_iter()
+        .map(|(_, province)| province)
+        .filter(|province| !province.sectors.is_empty())
+        .collect();
+
+    if settings.province_seeds == ProvinceSeeds::Random {
+        for province in &mut provinces {
+            province.capital = (0..cities.len())
+                .filter(|i| province.sectors.binary_search(&cities[*i].sector).is_ok())
+                .max_by_key(|i| (cities[*i].population, Reverse(*i)));
+        }
+    }
+
+    provinces
+}
+
+/// Give every land sector to the seed that can reach it most cheaply over land,
+/// returning the index of its seed, or `None` for water and land no seed can reach,
+/// such as an island without a city.
+/// Crossing sectors costs the same as it does for roads, so borders settle along
+/// mountains and rivers, where the cost of reaching further climbs steeply.
+pub fn grow_provinces(
+    seeds: &[usize],
+    is_land: &[bool],
+    centroids: &[Vec2],
+    costs: &[f32],
+    neighbors: &[&[usize]],
+) -> Vec<Option<usize>> {
+    let mut owners: Vec<Option<usize>> = vec![None; is_land.len()];
+    let mut distances = vec![f32::INFINITY; is_land.len()];
+
+    // Distances are never negative, so their bit patterns sort in the same order as the floats.
+    // Ties go to the seed that reached a sector first, so the result doesn't depend on hashing.
+    let mut queue = BinaryHeap::new();
+    for (seed, sector) in seeds.iter().enumerate() {
+        if owners[*sector].is_none() {
+            owners[*sector] = Some(seed);
+            distances[*sector] = 0.0;
+            queue.push(Reverse((0.0f32.to_bits(), *sector)));
+        }
+    }
+
+    while let Some(Reverse((distance, i))) = queue.pop() {
+        let distance = f32::from_bits(distance);
+        if distance > distances[i] {
+            continue;
+        }
+
+        for j in neighbors[i].iter().copied().filter(|j| is_land[*j]) {
+            let step = 0.5 * centroids[i].distance(centroids[j]) * (costs[i] + costs[j]);
+            if distance + step < distances[j] {
+                distances[j] = distance + step;
+                owners[j] = owners[i];
+                queue.push(Reverse(((distance + step).to_bits(), j)));
+            }
+        }
+    }
+
+    owners
+}
+
+/// Outline every province with lines along the edges where it meets another province.
+#[cfg(feature = "render")]
+pub fn add_province_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    map_query: Query<Entity, With<Map>>,
+    province_query: Query<(Entity, &Province)>,
+    sector_query: Query<&Sector>,
+    settings: Res<DisplaySettings>,
+) {
+    let e_map = map_query.single().unwrap();
+    let owners: HashMap<Entity, Entity> = province_query
+        .iter()
+        .flat_map(|(e_province, province)| {
+            province
+                .sectors
+                .iter()
+                .map(move |e_sector| (*e_sector, e_province))
+        })
+        .collect();
+
+    let half_width = settings.province_border_width / 2.0;
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut triangles: Vec<u32> = Vec::new();
+
+    for (e_province, province) in &province_query {
+        for e_sector in &province.sectors {
+            let sector = sector_query.get(*e_sector).unwrap();
+
+            // Every edge is shared by two sectors, so only draw it from the first one
+            for e_neighbor in sector.neighbors.iter().filter(|e| *e > e_sector) {
+                if owners
+                    .get(e_neighbor)
+                    .is_none_or(|e_other| *e_other == e_province)
+                {
+                    continue;
+                }
+                let neighbor = sector_query.get(*e_neighbor).unwrap();
+                let Some((start, end)) = shared_edge(&sector.border, &neighbor.border) else {
+                    continue;
+                };
+
+                // Lengthen each line a little so lines meeting at a corner overlap
+                let direction = (end - start).normalize_or_zero();
+                let start = start - direction * half_width;
+                let end = end + direction * half_width;
+                let side = direction.perp() * half_width;
+
+                let index = positions.len() as u32;
+                for corner in [start + side, start - side, end - side, end + side] {
+                    positions.push(corner.extend(0.0));
+                }
+                triangles.extend([index, index + 1, index + 2, index, index + 2, index + 3]);
+            }
+        }
+    }
+
+    if positions.is_empty() {
+        return;
+    }
+
+    let mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_indices(Indices::U32(triangles));
+    let (r, g, b) = PROVINCE_BORDER_COLOR;
+    let mesh_entity = commands
+        .spawn((
+            Mesh2d(meshes.add(mesh)),
+            MeshMaterial2d(materials.add(Color::srgb_u8(r, g, b))),
+            Transform::from_xyz(0.0, 0.0, 0.15),
+        ))
+        .id();
+    commands.entity(e_map).add_child(mesh_entity);
+}
+
+/// The ends of the edge between two neighboring sectors.
+/// Neighboring voronoi cells are built from the same vertices, so they can be compared exactly.
+#[cfg(feature = "render")]
+fn shared_edge(border: &[Vec2], other: &[Vec2]) -> Option<(Vec2, Vec2)> {
+    let mut shared = border.iter().filter(|point| other.contains(point));
+    Some((*shared.next()?, *shared.next()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bevy_prng::WyRand;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// An 8 by 4 grid of sectors one unit apart, each joined to the four beside it,
+    /// with a column of sea at `x = 4` splitting the land in two.
+    fn split_grid(biomes: &BiomeRegistry) -> Vec<WorldSector> {
+        const WIDTH: usize = 8;
+        const HEIGHT: usize = 4;
+        let water = biomes.by_name("Water").unwrap();
+        let plains = biomes.by_name("Plains").unwrap();
+        (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let (x, y) = (i % WIDTH, i / WIDTH);
+                let centroid = vec2(x as f32, y as f32);
+                let neighbors = [
+                    (x > 0).then(|| i - 1),
+                    (x + 1 < WIDTH).then(|| i + 1),
+                    (y > 0).then(|| i - WIDTH),
+                    (y + 1 < HEIGHT).then(|| i + WIDTH),
+                ];
+                WorldSector {
+                    site: centroid,
+                    border: Vec::new(),
+                    centroid,
+                    height: 0.0,
+                    biome: Some(if x == 4 { water } else { plains }),
+                    water_body: None,
+                    coastal: false,
+                    cost: 1.0,
+                    river_flow: 0.0,
+                    downstream: None,
+                    neighbors: neighbors.into_iter().flatten().collect(),
+                }
+            })
+            .collect()
+    }
+
+    fn city(name: &str, sector: usize) -> WorldCity {
+        WorldCity {
+            name: name.to_string(),
+            sector,
+            position: Vec2::ZERO,
+            population: 100,
+            resource_nodes: Vec::new(),
+        }
+    }
+
+    fn owners(provinces: &[WorldProvince], sector_num: usize) -> Vec<Vec<usize>> {
+        let mut owners = vec![Vec::new(); sector_num];
+        for (i, province) in provinces.iter().enumerate() {
+            for sector in &province.sectors {
+                owners[*sector].push(i);
+            }
+        }
+        owners
+    }
+
+    #[test]
+    fn land_sectors_never_share_a_province() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let sectors = split_grid(&biomes);
+        let cities = [city("Roma", 0), city("Ostia", 26), city("Capua", 15)];
+        let names = ["Latium".to_string(), "Campania".to_string()];
+
+        for province_seeds in [ProvinceSeeds::Cities, ProvinceSeeds::Random] {
+            let settings = MapGenSettings {
+                province_seeds,
+                province_num: 4,
+                ..MapGenSettings::default()
+            };
+            let provinces = generate_provinces(
+                &sectors,
+                &cities,
+                &names,
+                &settings,
+                &biomes,
+                &mut WyRand::seed_from_u64(3),
+            );
+
+            // Random seeds can all land on one side of the sea, leaving the other unowned
+            for (i, owners) in owners(&provinces, sectors.len()).iter().enumerate() {
+                if biomes.is_water(sectors[i].biome) {
+                    assert!(owners.is_empty(), "water sector {} has a province", i);
+                } else if province_seeds == ProvinceSeeds::Cities {
+                    assert_eq!(owners.len(), 1, "land sector {} has {:?}", i, owners);
+                } else {
+                    assert!(owners.len() <= 1, "land sector {} has {:?}", i, owners);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_city_governs_its_own_province() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let sectors = split_grid(&biomes);
+        let cities = [city("Roma", 0), city("Ostia", 26), city("Capua", 15)];
+        let settings = MapGenSettings {
+            province_seeds: ProvinceSeeds::Cities,
+            ..MapGenSettings::default()
+        };
+        let provinces = generate_provinces(
+            &sectors,
+            &cities,
+            &[],
+            &settings,
+            &biomes,
+            &mut WyRand::seed_from_u64(3),
+        );
+
+        assert_eq!(provinces.len(), cities.len());
+        for (i, city) in cities.iter().enumerate() {
+            let province = provinces
+                .iter()
+                .find(|province| province.capital == Some(i))
+                .unwrap();
+            assert_eq!(province.name, city.name);
+            assert!(province.sectors.contains(&city.sector));
+        }
+    }
+
+    #[test]
+    fn land_no_seed_can_reach_is_left_unowned() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let sectors = split_grid(&biomes);
+        let is_land: Vec<bool> = sectors
+            .iter()
+            .map(|sector| !biomes.is_water(sector.biome))
+            .collect();
+        let centroids: Vec<Vec2> = sectors.iter().map(|sector| sector.centroid).collect();
+        let costs = vec![1.0; sectors.len()];
+        let neighbors: Vec<&[usize]> = sectors
+            .iter()
+            .map(|sector| sector.neighbors.as_slice())
+            .collect();
+
+        // Both seeds are west of the sea, so the east is out of reach
+        let owners = grow_provinces(&[0, 24], &is_land, &centroids, &costs, &neighbors);
+        for (i, owner) in owners.iter().enumerate() {
+            if i % 8 < 4 {
+                assert!(owner.is_some(), "sector {} wasn't reached", i);
+            } else {
+                assert_eq!(*owner, None, "sector {} was reached", i);
+            }
+        }
+        assert_eq!(owners[0], Some(0));
+        assert_eq!(owners[24], Some(1));
+    }
+}
@@ -1,23 +1,24 @@
+#[cfg(feature = "render")]
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::{collections::HashMap, time::Duration};
 
 use bevy::prelude::*;
-use bevy_prng::WyRand;
-use bevy_rand::prelude::Entropy;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "debug")]
+use crate::city::City;
 use crate::{
-    biome::Biome,
-    city::City,
-    map::{Map, Sector},
-    settings::{GameplaySettings, MapGenSettings},
+    biome::BiomeRegistry,
+    settings::MapGenSettings,
     shipment::Shipment,
-    utils,
+    spatial::PointGrid,
     wagon::Wagon,
+    worldgen::{WorldCity, WorldNode, WorldSector},
 };
 
 /// A type of non-global resource.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Resource {
     Wheat,
     Ore,
@@ -47,30 +48,43 @@ pub struct ResourceNode {
 }
 
 /// A type of [`ResourceNode`]
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum ResourceNodeType {
     Farm,
     Mine,
     Lumbermill,
 }
 
-pub fn spawn_resource_nodes(
-    mut commands: Commands,
-    mut map_query: Query<(&Map, &mut Entropy<WyRand>)>,
-    city_query: Query<(Entity, &mut City, &Transform)>,
-    sector_query: Query<&Sector>,
-    settings: Res<MapGenSettings>,
-    gameplay_settings: Res<GameplaySettings>,
-) {
-    let (map, mut rng) = map_query.single_mut().unwrap();
+impl ResourceNodeType {
+    /// The color nodes of this type are drawn with, as sRGB.
+    pub fn color(self) -> (u8, u8, u8) {
+        match self {
+            Self::Farm => (218, 165, 32),
+            Self::Mine => (112, 128, 144),
+            Self::Lumbermill => (139, 69, 19),
+        }
+    }
+}
 
-    let mut node_positions: Vec<Vec2> = Vec::new();
-    let city_positions: Vec<Vec2> = city_query
-        .iter()
-        .map(|(_, _, t_city)| t_city.translation.xy())
-        .collect();
+/// The radius resource nodes are drawn with.
+pub const NODE_RADIUS: f32 = 5.0;
+
+/// Choose where each city's resource nodes go, recording them on the cities.
+pub fn place_resource_nodes(
+    sectors: &[WorldSector],
+    cities: &mut [WorldCity],
+    settings: &MapGenSettings,
+    biomes: &BiomeRegistry,
+    rng: &mut impl Rng,
+) -> Vec<WorldNode> {
+    let mut nodes: Vec<WorldNode> = Vec::new();
+    let mut placed_nodes = PointGrid::new(settings.node_min_spacing.max(1.0));
+    let mut city_positions = PointGrid::new(settings.node_city_max_dist.max(1.0));
+    for (city_index, city) in cities.iter().enumerate() {
+        city_positions.insert(city.position, city_index);
+    }
 
-    for (e_city, mut city, t_city) in city_query {
+    for (city_index, city) in cities.iter_mut().enumerate() {
         let mut nodes_to_gen = rng.random_range(settings.nodes_per_city_range.clone());
 
         for _ in 0..1000 {
@@ -78,10 +92,9 @@ pub fn spawn_resource_nodes(
                 break;
             }
 
-            let rand_index = rng.random_range(0..map.sectors.len());
-            let e_sector = map.sectors[rand_index];
-            let sector = sector_query.get(e_sector).unwrap();
-            let mut node_pos = sector.centroid;
+            let sector_index = rng.random_range(0..sectors.len());
+            let sector = &sectors[sector_index];
+            let node_pos = sector.centroid;
 
             // Check not outside of deadzone
             if node_pos.x < settings.node_deadzone
@@ -94,40 +107,41 @@ pub fn spawn_resource_nodes(
 
             // Check not too close to a city
             if city_positions
-                .iter()
-                .any(|pos| pos.distance(node_pos) < settings.node_city_min_dist)
+                .within_radius(node_pos, settings.node_city_min_dist)
+                .next()
+                .is_some()
             {
                 continue;
             }
 
             // Check not too close to a node
-            if node_positions
-                .iter()
-                .any(|pos| pos.distance(node_pos) < settings.node_min_spacing)
+            if placed_nodes
+                .within_radius(node_pos, settings.node_min_spacing)
+                .next()
+                .is_some()
             {
                 continue;
             }
 
             // Check close enough to this city
-            if node_pos.distance(t_city.translation.xy()) > settings.node_city_max_dist {
+            if node_pos.distance(city.position) > settings.node_city_max_dist {
                 continue;
             }
 
             // Check not closer to a different city
-
-            if city_positions.iter().any(|pos| {
-                !utils::vec2_equals(pos, &t_city.translation.xy())
-                    && node_pos.distance(*pos) < node_pos.distance(t_city.translation.xy())
-            }) {
+            let (nearest, nearest_pos) = city_positions.nearest(node_pos).unwrap();
+            if nearest != city_index
+                && node_pos.distance(nearest_pos) < node_pos.distance(city.position)
+            {
                 continue;
             }
 
-            // Get node type and check not in desert
-            let node_type = match sector.biome.unwrap() {
-                Biome::Plains => ResourceNodeType::Farm,
-                Biome::Forest => ResourceNodeType::Lumbermill,
-                Biome::Mountains => ResourceNodeType::Mine,
-                Biome::Desert | Biome::Water => continue,
+            // Get node type and check the biome has any
+            let node_types = &biomes.get(sector.biome.unwrap()).nodes;
+            let node_type = match node_types.len() {
+                0 => continue,
+                1 => node_types[0],
+                len => node_types[rng.random_range(0..len)],
             };
             let produces = match node_type {
                 ResourceNodeType::Farm => Resource::Wheat,
@@ -135,44 +149,26 @@ pub fn spawn_resource_nodes(
                 ResourceNodeType::Lumbermill => Resource::Lumber,
             };
 
-            node_positions.push(node_pos);
-
-            // Compensate for the change caused by adding as a child
-            node_pos = node_pos - t_city.translation.xy();
-
-            let wagon_timer = Timer::from_seconds(
-                gameplay_settings.node_wagon_spawn_time,
-                TimerMode::Repeating,
-            )
-            .tick(Duration::from_secs_f32(
-                rng.random_range(0.0..gameplay_settings.node_wagon_spawn_time),
-            ))
-            .clone();
-
-            let e_node = commands
-                .spawn((
-                    ResourceNode {
-                        node_type,
-                        produces: Shipment {
-                            resource: produces,
-                            quantity: 1,
-                        },
-                        sector: e_sector,
-                        wagon_timer,
-                        city: e_city,
-                        road: Entity::PLACEHOLDER,
-                    },
-                    Transform::from_translation(node_pos.extend(0.0)),
-                ))
-                .id();
-            commands.entity(e_city).add_child(e_node);
-            city.resource_nodes.push(e_node);
+            placed_nodes.insert(node_pos, nodes.len());
+            city.resource_nodes.push(nodes.len());
+            nodes.push(WorldNode {
+                node_type,
+                produces,
+                sector: sector_index,
+                city: city_index,
+                position: node_pos,
+                road: None,
+                timer_offset: rng.random_range(0.0..1.0),
+            });
 
             nodes_to_gen -= 1;
         }
     }
+
+    nodes
 }
 
+#[cfg(feature = "render")]
 pub fn add_node_meshes(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials_asset: ResMut<Assets<ColorMaterial>>,
@@ -180,19 +176,15 @@ pub fn add_node_meshes(
     node_query: Query<(Entity, &ResourceNode)>,
 ) {
     let mut materials: HashMap<ResourceNodeType, Handle<ColorMaterial>> = HashMap::new();
-    materials.insert(
+    for node_type in [
         ResourceNodeType::Farm,
-        materials_asset.add(Color::srgb_u8(218, 165, 32)),
-    );
-    materials.insert(
         ResourceNodeType::Lumbermill,
-        materials_asset.add(Color::srgb_u8(139, 69, 19)),
-    );
-    materials.insert(
         ResourceNodeType::Mine,
-        materials_asset.add(Color::srgb_u8(112, 128, 144)),
-    );
-    let mesh = meshes.add(Circle::new(5.0));
+    ] {
+        let (r, g, b) = node_type.color();
+        materials.insert(node_type, materials_asset.add(Color::srgb_u8(r, g, b)));
+    }
+    let mesh = meshes.add(Circle::new(NODE_RADIUS));
 
     for (e_node, node) in node_query {
         commands.entity(e_node).insert((
@@ -202,6 +194,7 @@ pub fn add_node_meshes(
     }
 }
 
+#[cfg(feature = "debug")]
 pub fn debug_relations(
     mut gizmos: Gizmos,
     node_query: Query<(&ResourceNode, &GlobalTransform)>,
@@ -0,0 +1,187 @@
+#[cfg(feature = "render")]
+use std::collections::HashSet;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use rand::Rng;
+
+#[cfg(feature = "render")]
+use bevy::prelude::*;
+
+use crate::{biome::BiomeRegistry, settings::MapGenSettings, worldgen::WorldSector};
+#[cfg(feature = "render")]
+use crate::{
+    map::{Map, Sector},
+    settings::DisplaySettings,
+    utils::{bezier_path, line_mesh},
+};
+
+/// The color rivers are drawn with, as sRGB.
+pub const RIVER_COLOR: (u8, u8, u8) = (64, 105, 225);
+
+/// How far each sector in a filled sink is raised above the sector it overflows into,
+/// so water never has to cross flat ground.
+const FILL_STEP: f32 = 1e-5;
+
+/// Trace rivers from random high sectors down to the water, recording on every sector
+/// how many rivers flow through it and which sector they flow into next.
+/// Sectors with a river cost more to cross. Roads pay that cost wherever they cross a river,
+/// as fords and bridges aren't modelled.
+pub fn generate_rivers(
+    sectors: &mut [WorldSector],
+    settings: &MapGenSettings,
+    biomes: &BiomeRegistry,
+    rng: &mut impl Rng,
+) {
+    let is_water: Vec<bool> = sectors
+        .iter()
+        .map(|sector| biomes.is_water(sector.biome) || sector.height < settings.water_cutoff)
+        .collect();
+
+    let sources: Vec<usize> = (0..sectors.len())
+        .filter(|i| !is_water[*i] && sectors[*i].height >= settings.river_source_min_height)
+        .collect();
+    if sources.is_empty() {
+        return;
+    }
+
+    let drains = drainage(sectors, &is_water);
+    for _ in 0..settings.river_num {
+        let mut current = sources[rng.random_range(0..sources.len())];
+
+        // Rivers that can't reach the water stop where they are
+        while !is_water[current] {
+            sectors[current].river_flow += 1.0;
+            sectors[current].downstream = drains[current];
+
+            match drains[current] {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+    }
+
+    for sector in sectors.iter_mut().filter(|sector| sector.river_flow > 0.0) {
+        sector.cost += settings.river_cost;
+    }
+}
+
+/// The neighbor each land sector drains into, or `None` for water and land that can't reach it.
+/// Sinks are filled up to the height they overflow at first, so every land sector that can reach
+/// the water drains into a neighbor strictly lower than itself, and rivers can never run in a circle.
+fn drainage(sectors: &[WorldSector], is_water: &[bool]) -> Vec<Option<usize>> {
+    let lowest = sectors
+        .iter()
+        .map(|sector| sector.height)
+        .fold(f32::INFINITY, f32::min);
+
+    // Flood the land up from the water, lowest first. Heights are measured from the lowest
+    // sector, so they are never negative and their bit patterns sort in the same order as the floats.
+    let mut filled = vec![f32::INFINITY; sectors.len()];
+    let mut queue = BinaryHeap::new();
+    for i in (0..sectors.len()).filter(|i| is_water[*i]) {
+        filled[i] = sectors[i].height - lowest;
+        queue.push(Reverse((filled[i].to_bits(), i)));
+    }
+    while let Some(Reverse((height, i))) = queue.pop() {
+        let height = f32::from_bits(height);
+        if height > filled[i] {
+            continue;
+        }
+        for j in sectors[i]
+            .neighbors
+            .iter()
+            .copied()
+            .filter(|j| !is_water[*j])
+        {
+            let raised = (sectors[j].height - lowest).max(height + FILL_STEP);
+            if raised < filled[j] {
+                filled[j] = raised;
+                queue.push(Reverse((raised.to_bits(), j)));
+            }
+        }
+    }
+
+    (0..sectors.len())
+        .map(|i| {
+            if is_water[i] {
+                return None;
+            }
+            sectors[i]
+                .neighbors
+                .iter()
+                .copied()
+                .min_by(|a, b| filled[*a].total_cmp(&filled[*b]))
+                .filter(|next| filled[*next] < filled[i])
+        })
+        .collect()
+}
+
+/// Draw every river as lines between sector centroids, wider where more water flows.
+#[cfg(feature = "render")]
+pub fn add_river_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    map_query: Query<(Entity, &Map)>,
+    sector_query: Query<&Sector>,
+    settings: Res<DisplaySettings>,
+) {
+    let (e_map, map) = map_query.single().unwrap();
+    let rivers: Vec<(Entity, &Sector)> = map
+        .sectors
+        .iter()
+        .map(|e_sector| (*e_sector, sector_query.get(*e_sector).unwrap()))
+        .filter(|(_, sector)| sector.river_flow > 0.0)
+        .collect();
+    let Some(max_flow) = rivers
+        .iter()
+        .map(|(_, sector)| sector.river_flow)
+        .reduce(f32::max)
+    else {
+        return;
+    };
+
+    // Draw one line from each source, stopping where it reaches the water or an earlier line
+    let fed: HashSet<Entity> = rivers
+        .iter()
+        .filter_map(|(_, sector)| sector.downstream)
+        .collect();
+    let mut drawn: HashSet<Entity> = HashSet::new();
+    let (r, g, b) = RIVER_COLOR;
+    let material = materials.add(Color::srgb_u8(r, g, b));
+
+    for (e_source, source) in rivers.iter().filter(|(e, _)| !fed.contains(e)) {
+        let mut points = vec![source.centroid];
+        let mut flow = source.river_flow;
+        let mut current = (*e_source, *source);
+
+        while drawn.insert(current.0) {
+            let Some(e_next) = current.1.downstream else {
+                break;
+            };
+            let next = sector_query.get(e_next).unwrap();
+            points.push(next.centroid);
+            if next.river_flow <= 0.0 {
+                break;
+            }
+            flow = flow.max(next.river_flow);
+            current = (e_next, next);
+        }
+
+        if points.len() < 2 {
+            continue;
+        }
+
+        let subdivisions = points.len() * 4;
+        let width = settings.river_width * (flow / max_flow).sqrt();
+        let mesh = line_mesh(&bezier_path(points, subdivisions), width / 2.0);
+        let mesh_entity = commands
+            .spawn((
+                Mesh2d(meshes.add(mesh)),
+                MeshMaterial2d(material.clone()),
+                Transform::from_xyz(0.0, 0.0, 0.1),
+            ))
+            .id();
+        commands.entity(e_map).add_child(mesh_entity);
+    }
+}
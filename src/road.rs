@@ -1,13 +1,17 @@
 use bevy::{prelude::*, render::render_resource::encase::private::Length};
 
+#[cfg(feature = "debug")]
+use crate::map::Sector;
+#[cfg(feature = "render")]
+use crate::{settings::DisplaySettings, utils::line_mesh};
 use crate::{
-    city::City,
-    map::Sector,
-    resource::ResourceNode,
-    settings::DisplaySettings,
-    utils::{bezier_pathfind, line_mesh},
+    utils::bezier_pathfind,
+    worldgen::{WorldCity, WorldNode, WorldRoad, WorldSector},
 };
 
+/// The color roads are drawn with, as sRGB.
+pub const ROAD_COLOR: (u8, u8, u8) = (210, 180, 140);
+
 #[derive(Component)]
 pub struct Road {
     pub start_sector: Entity,
@@ -26,35 +30,35 @@ pub struct Road {
     pub speed_multiplier: f32,
 }
 
-pub fn spawn_node_roads(
-    mut commands: Commands,
-    mut node_query: Query<&mut ResourceNode>,
-    city_query: Query<&City>,
-    sector_query: Query<&Sector>,
-) {
-    for city in city_query {
-        for e_node in &city.resource_nodes {
-            let mut node = node_query.get_mut(*e_node).unwrap();
-            let (curve, path) = bezier_pathfind(city.sector, node.sector, &sector_query).unwrap();
+/// Plan a road from every city to each of its resource nodes, recording them on the nodes.
+pub fn plan_node_roads(
+    sectors: &[WorldSector],
+    cities: &[WorldCity],
+    nodes: &mut [WorldNode],
+) -> Vec<WorldRoad> {
+    let mut roads: Vec<WorldRoad> = Vec::new();
+
+    for city in cities {
+        for node_index in &city.resource_nodes {
+            let node = &mut nodes[*node_index];
+            let (curve, path) = bezier_pathfind(city.sector, node.sector, sectors).unwrap();
             let length = curve.segments().length() as f32;
-            let e_road = commands
-                .spawn((
-                    Road {
-                        start_sector: city.sector,
-                        end_sector: node.sector,
-                        path,
-                        curve,
-                        length,
-                        speed_multiplier: 1.0,
-                    },
-                    Visibility::Visible,
-                ))
-                .id();
-            node.road = e_road;
+
+            node.road = Some(roads.len());
+            roads.push(WorldRoad {
+                start_sector: city.sector,
+                end_sector: node.sector,
+                path,
+                curve,
+                length,
+            });
         }
     }
+
+    roads
 }
 
+#[cfg(feature = "render")]
 pub fn add_road_meshes(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -62,6 +66,7 @@ pub fn add_road_meshes(
     road_query: Query<(Entity, &Road), Without<Mesh2d>>,
     settings: Res<DisplaySettings>,
 ) {
+    let (r, g, b) = ROAD_COLOR;
     for (e_road, road) in road_query {
         let points = road.curve.iter_positions(150).collect();
 
@@ -70,14 +75,16 @@ pub fn add_road_meshes(
         let mesh_entity = commands
             .spawn((
                 Mesh2d(mesh_handle),
-                MeshMaterial2d(materials.add(Color::srgb_u8(210, 180, 140))),
-                Transform::from_xyz(0.0, 0.0, 0.0),
+                MeshMaterial2d(materials.add(Color::srgb_u8(r, g, b))),
+                // Above rivers, so crossings look like bridges
+                Transform::from_xyz(0.0, 0.0, 0.2),
             ))
             .id();
         commands.entity(e_road).add_child(mesh_entity);
     }
 }
 
+#[cfg(feature = "debug")]
 pub fn debug_roads(mut gizmos: Gizmos, sector_query: Query<&Sector>, road_query: Query<&Road>) {
     for road in road_query {
         let mut path_iter = road.path.iter();
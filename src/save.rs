@@ -1,24 +1,1118 @@
-use std::time::Duration;
+use std::{
+    cmp::Reverse,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::{
+    ecs::system::SystemParam,
+    platform::collections::HashMap,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use bevy_prng::WyRand;
+use bevy_rand::prelude::Entropy;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
 
-use crate::settings::SaveSettings;
+#[cfg(feature = "render")]
+use crate::window::{EntryBuilder, WindowBuilder};
+use crate::{
+    GameConfig,
+    biome::BiomeRegistry,
+    city::{City, city_bundle},
+    click_off::Despawning,
+    demographic::{Demographic, JobType, Population},
+    history::{CultureInfo, EventType, HistoricalEvent, History, NeighborInfo, START_DATE},
+    map::{Map, Sector},
+    migration::{self, FORMAT_VERSION, SaveDocument},
+    province::Province,
+    resource::{ResourceNode, ResourceNodeType},
+    road::Road,
+    settings::{MapGenSettings, SaveSettings},
+    shipment::{Shipment, ShipmentReceiver},
+    utils,
+    wagon::Wagon,
+    water::WaterBody,
+};
+
+/// The file name prefix for autosave slots within the save directory.
+const AUTOSAVE_PREFIX: &str = "autosave";
+
+/// The longest name a save can be given in the save prompt.
+#[cfg(feature = "render")]
+const MAX_SAVE_NAME_LEN: usize = 64;
 
 /// Component that manages saving game data on a regular interval.
 /// Intended to be a singleton component.
 #[derive(Component)]
 pub struct SaveManager {
     timer: Timer,
+
+    /// The autosave being written in the background, if there is one.
+    autosave: Option<Task<()>>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Format(serde_json::Error),
+
+    /// A stored index doesn't point at anything in the save.
+    BadReference,
+
+    /// The save's header is missing or unreadable.
+    BadHeader,
+
+    /// The save uses a biome that isn't in the [`BiomeRegistry`].
+    UnknownBiome(String),
+
+    /// The save was written with a format version this build can't load.
+    UnsupportedVersion(u32),
+
+    /// The save couldn't be upgraded from a format version.
+    MigrationFailed(u32),
+
+    /// Following a river downstream leads back to where it started.
+    RiverCycle,
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Format(err) => write!(f, "invalid save data: {}", err),
+            Self::BadReference => write!(f, "save refers to an object that doesn't exist"),
+            Self::BadHeader => write!(f, "save header is missing or unreadable"),
+            Self::UnknownBiome(name) => write!(f, "save uses unknown biome {}", name),
+            Self::UnsupportedVersion(version) if *version > FORMAT_VERSION => write!(
+                f,
+                "save format version {} is newer than the latest supported version {}",
+                version, FORMAT_VERSION
+            ),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "save format version {} is not supported", version)
+            }
+            Self::MigrationFailed(version) => {
+                write!(f, "couldn't upgrade save from format version {}", version)
+            }
+            Self::RiverCycle => write!(f, "save has a river that flows in a circle"),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Format(value)
+    }
+}
+
+/// A complete save file.
+pub struct Save {
+    pub header: SaveHeader,
+    pub data: SaveData,
+}
+
+/// Information about a save, stored on the first line of the file.
+#[derive(Serialize, Deserialize)]
+pub struct SaveHeader {
+    /// The format version the save was written with.
+    pub version: u32,
+
+    pub name: String,
+    pub kind: SaveKind,
+
+    /// When the save was made.
+    pub timestamp: DateTime<Utc>,
+
+    /// The in-game date when the save was made.
+    pub date: NaiveDate,
+
+    /// The seed the map was generated from.
+    pub seed: u64,
+
+    /// The settings the map was generated with.
+    pub map_gen: MapGenSettings,
+}
+
+/// How a save was made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SaveKind {
+    Autosave,
+    Manual,
+}
+
+/// A save on disk, described by its header alone.
+pub struct SaveInfo {
+    pub path: PathBuf,
+    pub header: SaveHeader,
+}
+
+/// Event requesting a manual save under a name.
+#[derive(Event)]
+pub struct SaveRequest {
+    pub name: String,
+}
+
+/// A snapshot of the whole game state.
+/// Entity references are stored as indices into the lists in this struct.
+#[derive(Serialize, Deserialize)]
+pub struct SaveData {
+    /// Every sector, in the same order as [`Map::sectors`].
+    pub sectors: Vec<SectorData>,
+    pub cities: Vec<CityData>,
+    pub nodes: Vec<NodeData>,
+    pub roads: Vec<RoadData>,
+    pub wagons: Vec<WagonData>,
+    pub history: HistoryData,
+    pub provinces: Vec<ProvinceData>,
 }
 
-pub fn tick_save_manager(time: Res<Time>, mut query: Query<&mut SaveManager>) {
+/// Saved form of a [`Sector`].
+#[derive(Serialize, Deserialize)]
+pub struct SectorData {
+    pub site: Vec2,
+    pub border: Vec<Vec2>,
+    pub centroid: Vec2,
+    pub height: f32,
+    /// The name of the sector's biome in the [`BiomeRegistry`].
+    pub biome: Option<String>,
+    pub water_body: Option<WaterBody>,
+    pub coastal: bool,
+    pub cost: f32,
+    pub river_flow: f32,
+    pub downstream: Option<usize>,
+    pub neighbors: Vec<usize>,
+}
+
+/// Saved form of a [`City`] and its [`Demographic`] children.
+#[derive(Serialize, Deserialize)]
+pub struct CityData {
+    pub name: String,
+    pub position: Vec2,
+    pub sector: usize,
+    pub population: u32,
+    pub resource_nodes: Vec<usize>,
+    pub demographics: Vec<DemographicData>,
+
+    /// The total quantity of each resource the city has been sent, see [`ShipmentReceiver`].
+    pub received: Vec<Shipment>,
+
+    /// The city's [`CultureInfo`] neighbors, if the history sim has been set up.
+    pub neighbors: Option<Vec<NeighborData>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DemographicData {
+    pub job: JobType,
+    pub population: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NeighborData {
+    pub city: usize,
+    pub distance: f32,
+    pub road_distance: f32,
+}
+
+/// Saved form of a [`ResourceNode`].
+#[derive(Serialize, Deserialize)]
+pub struct NodeData {
+    pub node_type: ResourceNodeType,
+    pub produces: Shipment,
+
+    /// The position of this node in world space.
+    pub position: Vec2,
+    pub sector: usize,
+    pub wagon_timer: Timer,
+    pub city: usize,
+    pub road: usize,
+}
+
+/// Saved form of a [`Road`].
+#[derive(Serialize, Deserialize)]
+pub struct RoadData {
+    pub start_sector: usize,
+    pub end_sector: usize,
+    pub path: Vec<usize>,
+
+    /// The control points the road's curve is built from.
+    pub control_points: Vec<Vec2>,
+    pub length: f32,
+    pub speed_multiplier: f32,
+}
+
+/// Saved form of a [`Wagon`] in flight.
+#[derive(Serialize, Deserialize)]
+pub struct WagonData {
+    pub shipment: Shipment,
+    pub position: Vec2,
+    pub road: usize,
+    pub distance: f32,
+    pub destination: usize,
+}
+
+/// Saved form of a [`Province`].
+#[derive(Serialize, Deserialize)]
+pub struct ProvinceData {
+    pub name: String,
+    pub capital: Option<usize>,
+    pub sectors: Vec<usize>,
+}
+
+/// Saved form of the [`History`].
+#[derive(Serialize, Deserialize)]
+pub struct HistoryData {
+    pub events: Vec<EventData>,
+    pub date: NaiveDate,
+    pub day_progress: f32,
+}
+
+/// Saved form of a [`HistoricalEvent`].
+#[derive(Serialize, Deserialize)]
+pub struct EventData {
+    pub event_type: EventType,
+    pub date: NaiveDate,
+    pub involved: Vec<usize>,
+}
+
+impl SaveData {
+    /// Check that every stored index points at something in this save.
+    fn validate(&self) -> Result<(), SaveError> {
+        let check = |index: &usize, len: usize| -> Result<(), SaveError> {
+            if *index < len {
+                Ok(())
+            } else {
+                Err(SaveError::BadReference)
+            }
+        };
+        let sectors = self.sectors.len();
+        let cities = self.cities.len();
+
+        for sector in &self.sectors {
+            sector
+                .neighbors
+                .iter()
+                .try_for_each(|i| check(i, sectors))?;
+            sector
+                .downstream
+                .iter()
+                .try_for_each(|i| check(i, sectors))?;
+        }
+        self.check_rivers()?;
+        for city in &self.cities {
+            check(&city.sector, sectors)?;
+            city.resource_nodes
+                .iter()
+                .try_for_each(|i| check(i, self.nodes.len()))?;
+            for neighbor in city.neighbors.iter().flatten() {
+                check(&neighbor.city, cities)?;
+            }
+        }
+        for node in &self.nodes {
+            check(&node.sector, sectors)?;
+            check(&node.city, cities)?;
+            check(&node.road, self.roads.len())?;
+        }
+        for road in &self.roads {
+            check(&road.start_sector, sectors)?;
+            check(&road.end_sector, sectors)?;
+            road.path.iter().try_for_each(|i| check(i, sectors))?;
+            if road.control_points.is_empty() {
+                return Err(SaveError::BadReference);
+            }
+        }
+        for wagon in &self.wagons {
+            check(&wagon.road, self.roads.len())?;
+            check(&wagon.destination, cities)?;
+        }
+        for event in &self.history.events {
+            event.involved.iter().try_for_each(|i| check(i, cities))?;
+        }
+        for province in &self.provinces {
+            province.capital.iter().try_for_each(|i| check(i, cities))?;
+            province
+                .sectors
+                .iter()
+                .try_for_each(|i| check(i, sectors))?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that following any river downstream ends somewhere instead of going in a circle.
+    fn check_rivers(&self) -> Result<(), SaveError> {
+        // Sectors already known to lead somewhere, and those on the river being followed
+        let mut finished = vec![false; self.sectors.len()];
+        let mut on_river = vec![false; self.sectors.len()];
+
+        for start in 0..self.sectors.len() {
+            let mut river = Vec::new();
+            let mut current = Some(start);
+            while let Some(i) = current {
+                if finished[i] {
+                    break;
+                }
+                if on_river[i] {
+                    return Err(SaveError::RiverCycle);
+                }
+                on_river[i] = true;
+                river.push(i);
+                current = self.sectors[i].downstream;
+            }
+
+            for i in river {
+                finished[i] = true;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Everything saved about a city.
+type CityQueryData = (
+    Entity,
+    &'static City,
+    &'static Population,
+    &'static Transform,
+    &'static ShipmentReceiver,
+    Option<&'static CultureInfo>,
+);
+
+/// All the queries needed to take a snapshot of the game state.
+#[derive(SystemParam)]
+pub struct SaveQueries<'w, 's> {
+    map: Query<'w, 's, &'static Map>,
+    sectors: Query<'w, 's, &'static Sector>,
+    cities: Query<'w, 's, CityQueryData>,
+    demographics: Query<'w, 's, &'static Demographic>,
+    nodes: Query<'w, 's, (&'static ResourceNode, &'static GlobalTransform)>,
+    roads: Query<'w, 's, (Entity, &'static Road)>,
+    wagons: Query<'w, 's, (&'static Wagon, &'static Transform), Without<Despawning>>,
+    history: Query<'w, 's, &'static History>,
+    provinces: Query<'w, 's, &'static Province>,
+    map_gen: Res<'w, MapGenSettings>,
+    biomes: Res<'w, BiomeRegistry>,
+}
+
+impl SaveQueries<'_, '_> {
+    /// Capture the current game state under a name, or `None` if there is no map yet.
+    pub fn snapshot(&self, name: &str, kind: SaveKind) -> Option<Save> {
+        let map = self.map.single().ok()?;
+
+        let e_cities: Vec<Entity> = self.cities.iter().map(|(e_city, ..)| e_city).collect();
+        let e_nodes: Vec<Entity> = self
+            .cities
+            .iter()
+            .flat_map(|(_, city, ..)| city.resource_nodes.iter().copied())
+            .collect();
+        let e_roads: Vec<Entity> = self.roads.iter().map(|(e_road, _)| e_road).collect();
+
+        let sector_indices = index_map(&map.sectors);
+        let city_indices = index_map(&e_cities);
+        let node_indices = index_map(&e_nodes);
+        let road_indices = index_map(&e_roads);
+
+        let sectors = map
+            .sectors
+            .iter()
+            .map(|e_sector| {
+                let sector = self.sectors.get(*e_sector).unwrap();
+                SectorData {
+                    site: sector.site,
+                    border: sector.border.clone(),
+                    centroid: sector.centroid,
+                    height: sector.height,
+                    biome: sector
+                        .biome
+                        .map(|biome| self.biomes.get(biome).name.clone()),
+                    water_body: sector.water_body,
+                    coastal: sector.coastal,
+                    cost: sector.cost,
+                    river_flow: sector.river_flow,
+                    downstream: sector.downstream.map(|e| sector_indices[&e]),
+                    neighbors: sector.neighbors.iter().map(|e| sector_indices[e]).collect(),
+                }
+            })
+            .collect();
+
+        let cities = e_cities
+            .iter()
+            .map(|e_city| {
+                let (_, city, population, t_city, receiver, culture) =
+                    self.cities.get(*e_city).unwrap();
+                CityData {
+                    name: city.name.clone(),
+                    position: t_city.translation.xy(),
+                    sector: sector_indices[&city.sector],
+                    population: population.population,
+                    resource_nodes: city
+                        .resource_nodes
+                        .iter()
+                        .map(|e| node_indices[e])
+                        .collect(),
+                    demographics: {
+                        // Sorted so saving the same game always gives the same file
+                        let mut demographics: Vec<DemographicData> = city
+                            .demographics
+                            .values()
+                            .map(|e_demo| {
+                                let demo = self.demographics.get(*e_demo).unwrap();
+                                DemographicData {
+                                    job: demo.job.clone(),
+                                    population: demo.population,
+                                }
+                            })
+                            .collect();
+                        demographics.sort_by(|a, b| a.job.cmp(&b.job));
+                        demographics
+                    },
+                    received: receiver
+                        .total_received()
+                        .iter()
+                        .map(|(resource, quantity)| Shipment {
+                            resource: *resource,
+                            quantity: *quantity,
+                        })
+                        .collect(),
+                    neighbors: culture.map(|culture| {
+                        // Sorted so saving the same game always gives the same file
+                        let mut neighbors: Vec<NeighborData> = culture
+                            .neighbors
+                            .values()
+                            .map(|info| NeighborData {
+                                city: city_indices[&info.neighbor],
+                                distance: info.distance,
+                                road_distance: info.road_distance,
+                            })
+                            .collect();
+                        neighbors.sort_by_key(|neighbor| neighbor.city);
+                        neighbors
+                    }),
+                }
+            })
+            .collect();
+
+        let nodes = e_nodes
+            .iter()
+            .map(|e_node| {
+                let (node, t_node) = self.nodes.get(*e_node).unwrap();
+                NodeData {
+                    node_type: node.node_type,
+                    produces: node.produces,
+                    position: t_node.translation().xy(),
+                    sector: sector_indices[&node.sector],
+                    wagon_timer: node.wagon_timer.clone(),
+                    city: city_indices[&node.city],
+                    road: road_indices[&node.road],
+                }
+            })
+            .collect();
+
+        let roads = e_roads
+            .iter()
+            .map(|e_road| {
+                let (_, road) = self.roads.get(*e_road).unwrap();
+                RoadData {
+                    start_sector: sector_indices[&road.start_sector],
+                    end_sector: sector_indices[&road.end_sector],
+                    path: road.path.iter().map(|e| sector_indices[e]).collect(),
+                    control_points: road
+                        .path
+                        .iter()
+                        .map(|e| self.sectors.get(*e).unwrap().centroid)
+                        .collect(),
+                    length: road.length,
+                    speed_multiplier: road.speed_multiplier,
+                }
+            })
+            .collect();
+
+        let wagons = self
+            .wagons
+            .iter()
+            .map(|(wagon, t_wagon)| WagonData {
+                shipment: wagon.shipment,
+                position: t_wagon.translation.xy(),
+                road: road_indices[&wagon.road],
+                distance: wagon.distance,
+                destination: city_indices[&wagon.destination],
+            })
+            .collect();
+
+        let history = match self.history.single() {
+            Ok(history) => HistoryData {
+                events: history
+                    .events
+                    .iter()
+                    .map(|event| EventData {
+                        event_type: event.event_type,
+                        date: event.date,
+                        involved: event.involved.iter().map(|e| city_indices[e]).collect(),
+                    })
+                    .collect(),
+                date: history.date,
+                day_progress: history.day_progress,
+            },
+            Err(_) => HistoryData {
+                events: Vec::new(),
+                date: START_DATE,
+                day_progress: 0.0,
+            },
+        };
+
+        let provinces = self
+            .provinces
+            .iter()
+            .map(|province| ProvinceData {
+                name: province.name.clone(),
+                capital: province.capital.map(|e| city_indices[&e]),
+                sectors: province.sectors.iter().map(|e| sector_indices[e]).collect(),
+            })
+            .collect();
+
+        Some(Save {
+            header: SaveHeader {
+                version: FORMAT_VERSION,
+                name: name.to_string(),
+                kind,
+                timestamp: Utc::now(),
+                date: history.date,
+                seed: map.seed,
+                map_gen: self.map_gen.clone(),
+            },
+            data: SaveData {
+                sectors,
+                cities,
+                nodes,
+                roads,
+                wagons,
+                history,
+                provinces,
+            },
+        })
+    }
+}
+
+/// Map each entity in a list to its index in that list.
+fn index_map(entities: &[Entity]) -> HashMap<Entity, usize> {
+    entities
+        .iter()
+        .enumerate()
+        .map(|(index, entity)| (*entity, index))
+        .collect()
+}
+
+/// Write a save to a file, creating the save directory if needed.
+/// The header goes on the first line and the game state on the second.
+/// The save is written to a temporary file first so a crash can't leave a half-written save.
+pub fn write_save(path: &Path, save: &Save) -> Result<(), SaveError> {
+    let tmp_path = write_temp_save(path, save)?;
+    fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
+/// Write a save to a temporary file next to `path`, returning the temporary file's path.
+/// The temporary file is removed again if the save can't be written.
+fn write_temp_save(path: &Path, save: &Save) -> Result<PathBuf, SaveError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    if let Err(err) = write_save_contents(&tmp_path, save) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+
+    Ok(tmp_path)
+}
+
+fn write_save_contents(path: &Path, save: &Save) -> Result<(), SaveError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, &save.header)?;
+    writer.write_all(b"\n")?;
+    serde_json::to_writer(&mut writer, &save.data)?;
+    writer.into_inner().map_err(|err| err.into_error())?;
+    Ok(())
+}
+
+/// Write an autosave into the newest slot, shifting older autosaves back and dropping the oldest.
+/// The older autosaves are only shifted once the new one has been written,
+/// so a failed autosave leaves every slot as it was.
+pub fn write_autosave(dir: &Path, slots: u32, save: &Save) -> Result<(), SaveError> {
+    if slots == 0 {
+        return Ok(());
+    }
+
+    let slot_path = |slot: u32| dir.join(format!("{}-{}.json", AUTOSAVE_PREFIX, slot));
+    let tmp_path = write_temp_save(&slot_path(0), save)?;
+
+    let oldest = slot_path(slots - 1);
+    if oldest.exists() {
+        fs::remove_file(oldest)?;
+    }
+    for slot in (0..slots - 1).rev() {
+        let path = slot_path(slot);
+        if path.exists() {
+            fs::rename(path, slot_path(slot + 1))?;
+        }
+    }
+
+    fs::rename(tmp_path, slot_path(0))?;
+
+    Ok(())
+}
+
+/// Get the file name for a manual save, replacing characters that aren't safe in file names.
+/// Names that would be taken for an autosave slot get a suffix, so manual saves are never
+/// overwritten or rotated away by autosaves.
+fn save_file_name(name: &str) -> String {
+    let mut stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if stem.is_empty() {
+        stem = "Unnamed".to_string();
+    }
+
+    // Compared in lowercase, as some file systems don't tell case apart
+    let is_slot = stem
+        .to_lowercase()
+        .strip_prefix(AUTOSAVE_PREFIX)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|slot| !slot.is_empty() && slot.chars().all(|c| c.is_ascii_digit()));
+    if is_slot {
+        stem.push_str("_manual");
+    }
+
+    format!("{}.json", stem)
+}
+
+/// Parse the first line of a save into a document with no game state.
+fn read_document_header(reader: &mut impl BufRead) -> Result<SaveDocument, SaveError> {
+    let mut header_line = String::new();
+    reader.read_line(&mut header_line)?;
+
+    let doc = SaveDocument {
+        header: serde_json::from_str(&header_line).map_err(|_| SaveError::BadHeader)?,
+        world: None,
+    };
+
+    // Check the version before anything parses the rest of a potentially huge file.
+    let version = doc.version()?;
+    if version > FORMAT_VERSION {
+        return Err(SaveError::UnsupportedVersion(version));
+    }
+
+    Ok(doc)
+}
+
+/// Read only the header of a save, upgrading it to the current format version if needed.
+pub fn read_header(path: &Path) -> Result<SaveHeader, SaveError> {
+    let mut doc = read_document_header(&mut BufReader::new(File::open(path)?))?;
+    migration::migrate(&mut doc)?;
+    Ok(serde_json::from_value(doc.header)?)
+}
+
+/// Read a save from a file, upgrading it to the current format version if needed.
+pub fn read_save(path: &Path) -> Result<Save, SaveError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut doc = read_document_header(&mut reader)?;
+    doc.world = Some(serde_json::from_reader(reader)?);
+    migration::migrate(&mut doc)?;
+
+    let save = Save {
+        header: serde_json::from_value(doc.header)?,
+        data: serde_json::from_value(doc.world.unwrap())?,
+    };
+    save.data.validate()?;
+    Ok(save)
+}
+
+/// List the saves in a directory from their headers alone, newest first.
+/// Files that can't be read as saves are skipped.
+pub fn list_saves(dir: &Path) -> Result<Vec<SaveInfo>, SaveError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut saves = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let Ok(header) = read_header(&path) else {
+            continue;
+        };
+        saves.push(SaveInfo { path, header });
+    }
+
+    saves.sort_by_key(|save| Reverse(save.header.timestamp));
+    Ok(saves)
+}
+
+/// Spawn a saved game into the world, remapping every stored index to a new entity.
+/// Nothing is spawned if the save uses biomes that aren't in the registry.
+pub fn spawn_save(
+    commands: &mut Commands,
+    save: &Save,
+    biomes: &BiomeRegistry,
+) -> Result<(), SaveError> {
+    let data = &save.data;
+
+    let sector_biomes = data
+        .sectors
+        .iter()
+        .map(|sector| {
+            sector
+                .biome
+                .as_deref()
+                .map(|name| {
+                    biomes
+                        .by_name(name)
+                        .ok_or_else(|| SaveError::UnknownBiome(name.to_string()))
+                })
+                .transpose()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut reserve =
+        |len: usize| -> Vec<Entity> { (0..len).map(|_| commands.spawn_empty().id()).collect() };
+    let e_sectors = reserve(data.sectors.len());
+    let e_cities = reserve(data.cities.len());
+    let e_nodes = reserve(data.nodes.len());
+    let e_roads = reserve(data.roads.len());
+
+    let remap = |entities: &[Entity], indices: &[usize]| -> Vec<Entity> {
+        indices.iter().map(|i| entities[*i]).collect()
+    };
+
+    let e_map = commands
+        .spawn((
+            Map {
+                sectors: e_sectors.clone(),
+                seed: save.header.seed,
+            },
+            Entropy::<WyRand>::seed_from_u64(save.header.seed),
+            Transform::IDENTITY,
+            Visibility::Visible,
+        ))
+        .id();
+    commands.entity(e_map).add_children(&e_sectors);
+
+    for ((sector, biome), e_sector) in data.sectors.iter().zip(sector_biomes).zip(&e_sectors) {
+        commands.entity(*e_sector).insert(Sector {
+            site: sector.site,
+            border: sector.border.clone(),
+            centroid: sector.centroid,
+            height: sector.height,
+            biome,
+            water_body: sector.water_body,
+            coastal: sector.coastal,
+            cost: sector.cost,
+            river_flow: sector.river_flow,
+            downstream: sector.downstream.map(|i| e_sectors[i]),
+            neighbors: remap(&e_sectors, &sector.neighbors),
+        });
+    }
+
+    for (city, e_city) in data.cities.iter().zip(&e_cities) {
+        let mut demographics = HashMap::new();
+        for demo in &city.demographics {
+            let e_demo = commands
+                .spawn(Demographic {
+                    population: demo.population,
+                    job: demo.job.clone(),
+                })
+                .id();
+            commands.entity(*e_city).add_child(e_demo);
+            demographics.insert(demo.job.clone(), e_demo);
+        }
+
+        commands.entity(*e_city).insert(city_bundle(
+            City {
+                name: city.name.clone(),
+                resource_nodes: remap(&e_nodes, &city.resource_nodes),
+                sector: e_sectors[city.sector],
+                demographics,
+            },
+            city.population,
+            city.position,
+        ));
+        commands
+            .entity(*e_city)
+            .insert(ShipmentReceiver::with_received(
+                city.received
+                    .iter()
+                    .map(|shipment| (shipment.resource, shipment.quantity))
+                    .collect(),
+            ));
+
+        if let Some(neighbors) = &city.neighbors {
+            let neighbors = neighbors
+                .iter()
+                .map(|info| {
+                    let e_neighbor = e_cities[info.city];
+                    (
+                        e_neighbor,
+                        NeighborInfo::new(e_neighbor, info.distance, info.road_distance),
+                    )
+                })
+                .collect();
+            commands.entity(*e_city).insert(CultureInfo::new(neighbors));
+        }
+    }
+
+    for (node, e_node) in data.nodes.iter().zip(&e_nodes) {
+        // Nodes are children of their city, so their transform is relative to it
+        let city_pos = data.cities[node.city].position;
+        commands.entity(*e_node).insert((
+            ResourceNode {
+                node_type: node.node_type,
+                produces: node.produces,
+                sector: e_sectors[node.sector],
+                wagon_timer: node.wagon_timer.clone(),
+                city: e_cities[node.city],
+                road: e_roads[node.road],
+            },
+            Transform::from_translation((node.position - city_pos).extend(0.0)),
+        ));
+        commands.entity(e_cities[node.city]).add_child(*e_node);
+    }
+
+    for (road, e_road) in data.roads.iter().zip(&e_roads) {
+        commands.entity(*e_road).insert((
+            Road {
+                start_sector: e_sectors[road.start_sector],
+                end_sector: e_sectors[road.end_sector],
+                path: remap(&e_sectors, &road.path),
+                curve: utils::bezier_curve(road.control_points.clone()),
+                length: road.length,
+                speed_multiplier: road.speed_multiplier,
+            },
+            Visibility::Visible,
+        ));
+    }
+
+    for wagon in &data.wagons {
+        commands.spawn((
+            Wagon {
+                shipment: wagon.shipment,
+                road: e_roads[wagon.road],
+                distance: wagon.distance,
+                destination: e_cities[wagon.destination],
+            },
+            Transform::from_translation(wagon.position.extend(0.0)),
+        ));
+    }
+
+    let e_provinces: Vec<Entity> = data
+        .provinces
+        .iter()
+        .map(|province| {
+            commands
+                .spawn(Province {
+                    name: province.name.clone(),
+                    capital: province.capital.map(|i| e_cities[i]),
+                    sectors: remap(&e_sectors, &province.sectors),
+                })
+                .id()
+        })
+        .collect();
+    commands.entity(e_map).add_children(&e_provinces);
+
+    commands.spawn(History {
+        events: data
+            .history
+            .events
+            .iter()
+            .map(|event| HistoricalEvent {
+                event_type: event.event_type,
+                date: event.date,
+                involved: remap(&e_cities, &event.involved),
+            })
+            .collect(),
+        date: data.history.date,
+        day_progress: data.history.day_progress,
+    });
+
+    Ok(())
+}
+
+/// Load the save given in the [`GameConfig`] in place of generating a map.
+/// If it can't be loaded nothing is spawned, so a new map is generated instead.
+pub fn load_game(mut commands: Commands, config: Res<GameConfig>, biomes: Res<BiomeRegistry>) {
+    let Some(path) = &config.load else {
+        return;
+    };
+    info!("Loading save {}", path.display());
+
+    let result = read_save(path).and_then(|save| {
+        spawn_save(&mut commands, &save, &biomes)?;
+        Ok(save)
+    });
+    match result {
+        Ok(save) => commands.insert_resource(save.header.map_gen),
+        Err(err) => error!(
+            "Failed to load save {}: {}, generating a new map instead",
+            path.display(),
+            err
+        ),
+    }
+}
+
+/// Request a manual save named after the current time.
+pub fn quicksave(mut requests: EventWriter<SaveRequest>) {
+    requests.write(SaveRequest {
+        name: format!("Quicksave {}", Local::now().format("%Y-%m-%d %H-%M-%S")),
+    });
+}
+
+/// Marker component for the window asking for a name to save under,
+/// holding the name typed so far.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct SavePrompt {
+    name: String,
+}
+
+/// Open a window asking for a name to save the game under, if there isn't one open already.
+#[cfg(feature = "render")]
+pub fn open_save_prompt(mut commands: Commands, prompt_query: Query<(), With<SavePrompt>>) {
+    if prompt_query.is_empty() {
+        spawn_save_prompt(&mut commands, String::new());
+    }
+}
+
+#[cfg(feature = "render")]
+fn spawn_save_prompt(commands: &mut Commands, name: String) {
+    let e_window = WindowBuilder::new()
+        .width(Val::Auto)
+        .height(Val::Auto)
+        .left(Val::Px(10.0))
+        .top(Val::Px(60.0))
+        .add_entry(EntryBuilder::text("Save as").centered())
+        .add_entry(EntryBuilder::text(&format!("{}_", name)))
+        .add_entry(EntryBuilder::text("Enter to save, Esc to cancel"))
+        .spawn(commands);
+    commands.entity(e_window).insert(SavePrompt { name });
+}
+
+/// Type into the open save prompt. Enter saves under the typed name and escape cancels.
+#[cfg(feature = "render")]
+pub fn type_save_name(
+    mut commands: Commands,
+    mut keys: EventReader<KeyboardInput>,
+    prompt_query: Query<(Entity, &SavePrompt), Without<Despawning>>,
+    mut requests: EventWriter<SaveRequest>,
+) {
+    let Ok((e_prompt, prompt)) = prompt_query.single() else {
+        keys.clear();
+        return;
+    };
+
+    let mut name = prompt.name.clone();
+    let mut closed = false;
+    for key in keys.read().filter(|key| key.state.is_pressed()) {
+        match &key.logical_key {
+            Key::Character(text) => name.extend(text.chars().filter(|c| !c.is_control())),
+            Key::Space => name.push(' '),
+            Key::Backspace => {
+                name.pop();
+            }
+            Key::Enter if !name.trim().is_empty() => {
+                requests.write(SaveRequest {
+                    name: name.trim().to_string(),
+                });
+                closed = true;
+            }
+            Key::Escape => closed = true,
+            _ => {}
+        }
+        if closed {
+            break;
+        }
+    }
+    let name: String = name.chars().take(MAX_SAVE_NAME_LEN).collect();
+
+    // The window is replaced to show the new name, like the overlay legend
+    if closed || name != prompt.name {
+        commands.entity(e_prompt).insert(Despawning);
+    }
+    if !closed && name != prompt.name {
+        spawn_save_prompt(&mut commands, name);
+    }
+}
+
+/// Autosave on a regular interval and handle manual save requests.
+/// The game state is captured straight away, but saves are written in the background
+/// so large maps don't stall the game.
+pub fn tick_save_manager(
+    time: Res<Time>,
+    settings: Res<SaveSettings>,
+    mut query: Query<&mut SaveManager>,
+    mut requests: EventReader<SaveRequest>,
+    save_queries: SaveQueries,
+) {
     let Ok(mut saver) = query.single_mut() else {
         return;
     };
     saver.timer.tick(time.delta());
 
     if saver.timer.finished() {
-        println!("Saving...");
+        if saver
+            .autosave
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+        {
+            info!("Skipping autosave, the last one is still being written");
+        } else if let Some(save) = save_queries.snapshot("Autosave", SaveKind::Autosave) {
+            info!("Autosaving...");
+            let dir = settings.save_directory.to_path_buf();
+            let slots = settings.autosave_slots;
+            saver.autosave = Some(AsyncComputeTaskPool::get().spawn(async move {
+                if let Err(err) = write_autosave(&dir, slots, &save) {
+                    error!("Failed to autosave: {}", err);
+                }
+            }));
+        }
+    }
+
+    for request in requests.read() {
+        let Some(save) = save_queries.snapshot(&request.name, SaveKind::Manual) else {
+            continue;
+        };
+        let path = settings.save_directory.join(save_file_name(&request.name));
+        info!("Saving to {}...", path.display());
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                if let Err(err) = write_save(&path, &save) {
+                    error!("Failed to save: {}", err);
+                }
+            })
+            .detach();
     }
 }
 
@@ -28,5 +1122,154 @@ pub fn spawn_save_manager(mut commands: Commands, settings: Res<SaveSettings>) {
             Duration::from_secs_f32(settings.save_interval),
             TimerMode::Repeating,
         ),
+        autosave: None,
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use bevy::ecs::system::{RunSystemOnce, SystemState};
+
+    use super::*;
+    use crate::{city_names, history, map_image::MapImages, settings::GameplaySettings, worldgen};
+
+    /// A directory of its own under the system's temporary directory for a test to write to.
+    fn test_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rome-game-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// An app with no systems besides transform propagation, ready to spawn a game into.
+    fn empty_app(settings: &MapGenSettings, biomes: &BiomeRegistry) -> App {
+        let mut app = App::new();
+        app.add_plugins(TransformPlugin)
+            .insert_resource(settings.clone())
+            .insert_resource(biomes.clone());
+        app
+    }
+
+    fn snapshot(app: &mut App, name: &str, kind: SaveKind) -> Save {
+        let mut state = SystemState::<SaveQueries>::new(app.world_mut());
+        state.get_mut(app.world_mut()).snapshot(name, kind).unwrap()
+    }
+
+    /// A save of a small generated world with its history set up.
+    fn generated_save(biomes: &BiomeRegistry) -> Save {
+        let settings = MapGenSettings {
+            sector_num: 2000,
+            ..MapGenSettings::default()
+        };
+        let names = city_names::read_name_file(Path::new("assets/city-names.csv")).unwrap();
+        let world = worldgen::generate_world(&settings, biomes, &MapImages::default(), 7, &names);
+
+        let mut app = empty_app(&settings, biomes);
+        let mut map = Map {
+            sectors: Vec::new(),
+            seed: world.seed,
+        };
+        let e_map = app.world_mut().spawn(Visibility::Visible).id();
+        worldgen::spawn_world(
+            &mut app.world_mut().commands(),
+            e_map,
+            &mut map,
+            &world,
+            &GameplaySettings::default(),
+        );
+        app.world_mut().flush();
+        app.world_mut().entity_mut(e_map).insert(map);
+        app.world_mut()
+            .run_system_once(history::setup_history_sim)
+            .unwrap();
+        app.update();
+
+        snapshot(&mut app, "Test", SaveKind::Manual)
+    }
+
+    #[test]
+    fn saves_round_trip() {
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let save = generated_save(&biomes);
+        assert!(!save.data.cities.is_empty());
+        assert!(!save.data.roads.is_empty());
+
+        let dir = test_dir("round-trip");
+        let path = dir.join("save.json");
+        write_save(&path, &save).unwrap();
+        let read = read_save(&path).unwrap();
+        assert_eq!(read_header(&path).unwrap().name, "Test");
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(read.header.version, FORMAT_VERSION);
+        assert_eq!(read.header.seed, save.header.seed);
+        assert_eq!(read.header.kind, SaveKind::Manual);
+        let data = serde_json::to_value(&save.data).unwrap();
+        assert_eq!(serde_json::to_value(&read.data).unwrap(), data);
+
+        // Loading the save and saving again must give back the same game
+        let mut app = empty_app(&read.header.map_gen, &biomes);
+        spawn_save(&mut app.world_mut().commands(), &read, &biomes).unwrap();
+        app.world_mut().flush();
+        app.update();
+        let resaved = snapshot(&mut app, "Test", SaveKind::Manual);
+        assert_eq!(serde_json::to_value(&resaved.data).unwrap(), data);
+    }
+
+    /// A save with no game in it, for tests that only care about files.
+    fn empty_save(name: &str, kind: SaveKind) -> Save {
+        Save {
+            header: SaveHeader {
+                version: FORMAT_VERSION,
+                name: name.to_string(),
+                kind,
+                timestamp: Utc::now(),
+                date: START_DATE,
+                seed: 0,
+                map_gen: MapGenSettings::default(),
+            },
+            data: SaveData {
+                sectors: Vec::new(),
+                cities: Vec::new(),
+                nodes: Vec::new(),
+                roads: Vec::new(),
+                wagons: Vec::new(),
+                history: HistoryData {
+                    events: Vec::new(),
+                    date: START_DATE,
+                    day_progress: 0.0,
+                },
+                provinces: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn autosaves_rotate_through_their_slots() {
+        let dir = test_dir("autosave");
+        for i in 0..4 {
+            let save = empty_save(&format!("Autosave {}", i), SaveKind::Autosave);
+            write_autosave(&dir, 3, &save).unwrap();
+        }
+
+        let names: Vec<String> = (0..4)
+            .map(|slot| dir.join(format!("{}-{}.json", AUTOSAVE_PREFIX, slot)))
+            .filter(|path| path.exists())
+            .map(|path| read_header(&path).unwrap().name)
+            .collect();
+        assert_eq!(names, ["Autosave 3", "Autosave 2", "Autosave 1"]);
+        assert!(fs::read_dir(&dir).unwrap().count() == 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn manual_saves_never_take_an_autosave_slot() {
+        assert_eq!(save_file_name("Via Appia"), "Via_Appia.json");
+        assert_eq!(save_file_name("autosave"), "autosave.json");
+        assert_eq!(save_file_name("autosave-x"), "autosave-x.json");
+        assert_eq!(save_file_name("autosave-0"), "autosave-0_manual.json");
+        assert_eq!(save_file_name("Autosave-12"), "Autosave-12_manual.json");
+        assert_eq!(save_file_name(""), "Unnamed.json");
+    }
+}
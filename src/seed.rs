@@ -0,0 +1,24 @@
+use rand::{TryRngCore, rngs::OsRng};
+
+use crate::fingerprint::StableHasher;
+
+/// Turn a seed given by the player into a map seed.
+/// Numbers are used as they are, anything else is treated as a phrase and hashed.
+pub fn parse_seed(text: &str) -> u64 {
+    text.parse().unwrap_or_else(|_| hash_phrase(text))
+}
+
+/// Hash a seed phrase such as `via-appia` into a map seed.
+/// This uses a [`StableHasher`] so the same phrase gives the same map on every build and platform.
+pub fn hash_phrase(phrase: &str) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write(phrase.as_bytes());
+    hasher.finish()
+}
+
+/// Pick a new map seed from OS entropy.
+pub fn random_seed() -> u64 {
+    OsRng
+        .try_next_u64()
+        .expect("Failed to get a random seed from the OS")
+}
@@ -1,20 +1,88 @@
+use std::fmt::Display;
+use std::fs;
+use std::io;
 use std::ops::Range;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Resource)]
+use crate::{biome::BiomeMode, province::ProvinceSeeds, shading::MapShading, shape::MapShape};
+
+/// Every group of settings, as stored in a settings file such as `assets/settings.ron`.
+/// Sections and fields missing from the file keep their default values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub map_gen: MapGenSettings,
+    pub gameplay: GameplaySettings,
+    pub display: DisplaySettings,
+    pub save: SaveSettings,
+}
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MapGenSettings {
     pub size: Vec2,
 
     pub sector_num: u32,
+    /// The closest two sector sites can be before one is rejected.
+    pub site_min_spacing: f32,
 
     pub lloyd_iters: u32,
     pub generator_border: f32,
     pub altitude_perlin_scale: f32,
     pub water_cutoff: f32,
 
+    /// A grayscale image to take sector heights from instead of noise and the [`MapShape`].
+    pub heightmap: Option<PathBuf>,
+    /// The heights black and white pixels in the heightmap stand for.
+    pub heightmap_range: Range<f32>,
+    /// A color-coded image to take sector biomes from, painted with the biome colors.
+    pub biome_map: Option<PathBuf>,
+
+    pub map_shape: MapShape,
+    /// How strongly the [`MapShape`] mask raises and sinks the land.
+    pub shape_strength: f32,
+
+    /// Rounds of erosion to run on the heights, or 0 to skip it.
+    pub erosion_iters: u32,
+    /// How much of its spare capacity a stream fills by cutting into the ground each round.
+    pub erosion_rate: f32,
+    /// How much sediment a stream carries per unit of slope and square root of its water.
+    pub sediment_capacity: f32,
+    /// How much of the sediment over a stream's capacity it drops each round.
+    pub deposition_rate: f32,
+    /// The steepest slope loose ground rests on, in height per unit of distance.
+    pub talus_slope: f32,
+    /// How much of the ground above the talus slope slumps downhill each round.
+    pub thermal_rate: f32,
+    /// The extra cost of crossing land per unit of slope, so roads avoid steep ground.
+    pub slope_cost: f32,
+
+    pub biome_mode: BiomeMode,
+    /// The number of biome seeds in [`BiomeMode::FloodFill`].
     pub biome_seed_num: u32,
+    pub south_temperature: f32,
+    pub north_temperature: f32,
+    /// How much the temperature drops per unit of height above the water.
+    pub temperature_lapse: f32,
+    /// The distance from water over which moisture falls by a factor of e.
+    pub moisture_falloff: f32,
+    /// The direction the prevailing wind blows towards.
+    pub wind_direction: Vec2,
+    /// How much rain the wind loses per unit of height it climbs.
+    pub rain_shadow: f32,
+
+    /// The fewest sectors enclosed water needs to be an inland sea rather than a lake.
+    pub inland_sea_min_size: u32,
+
+    pub river_num: u32,
+    /// The lowest height a river can start at.
+    pub river_source_min_height: f32,
+    /// The extra cost of crossing a sector with a river in it.
+    pub river_cost: f32,
 
     pub city_num: u32,
     pub city_min_spacing: f32,
@@ -26,21 +94,461 @@ pub struct MapGenSettings {
     pub node_city_min_dist: f32,
     pub node_min_spacing: f32,
     pub node_deadzone: f32,
+
+    pub province_seeds: ProvinceSeeds,
+    /// The number of provinces in [`ProvinceSeeds::Random`].
+    pub province_num: u32,
 }
 
-#[derive(Resource)]
+impl Default for MapGenSettings {
+    fn default() -> Self {
+        Self {
+            size: vec2(1000.0, 500.0),
+
+            sector_num: 16000,
+            site_min_spacing: 1.0,
+
+            lloyd_iters: 5,
+            generator_border: 20.0,
+            altitude_perlin_scale: 0.004,
+            water_cutoff: -0.6,
+
+            heightmap: None,
+            heightmap_range: -1.5..1.5,
+            biome_map: None,
+
+            map_shape: MapShape::Noise,
+            shape_strength: 1.0,
+
+            erosion_iters: 0,
+            erosion_rate: 0.1,
+            sediment_capacity: 0.5,
+            deposition_rate: 0.3,
+            talus_slope: 0.08,
+            thermal_rate: 0.5,
+            slope_cost: 0.0,
+
+            biome_mode: BiomeMode::Climate,
+            biome_seed_num: 120,
+            south_temperature: 1.0,
+            north_temperature: 0.3,
+            temperature_lapse: 0.5,
+            moisture_falloff: 80.0,
+            wind_direction: vec2(1.0, 0.0),
+            rain_shadow: 1.5,
+
+            inland_sea_min_size: 150,
+
+            river_num: 20,
+            river_source_min_height: 0.3,
+            river_cost: 20.0,
+
+            city_num: 10,
+            city_min_spacing: 100.0,
+            city_start_pop_range: 10..1000,
+            city_deadzone: 20.0,
+
+            nodes_per_city_range: 1..3,
+            node_city_max_dist: 100.0,
+            node_city_min_dist: 20.0,
+            node_min_spacing: 70.0,
+            node_deadzone: 10.0,
+
+            province_seeds: ProvinceSeeds::Cities,
+            province_num: 12,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DisplaySettings {
+    pub map_shading: MapShading,
+    /// How much the hillshade in [`MapShading::Relief`] darkens and lightens slopes, from 0 to 1.
+    pub hillshade_strength: f32,
+    /// How much steeper slopes are drawn than they are in [`MapShading::Relief`].
+    pub relief_exaggeration: f32,
+    /// The width of the square chunks the map mesh is split into.
+    /// Each is rebuilt on its own when one of its sectors changes.
+    pub map_chunk_size: f32,
+
     pub road_width: f32,
+
+    /// The width of the largest river; smaller rivers are drawn thinner.
+    pub river_width: f32,
+
+    /// The width of the lines between provinces.
+    pub province_border_width: f32,
 }
 
-#[derive(Resource)]
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            map_shading: MapShading::Flat,
+            hillshade_strength: 0.6,
+            relief_exaggeration: 20.0,
+            map_chunk_size: 100.0,
+
+            road_width: 4.0,
+            river_width: 3.0,
+            province_border_width: 1.5,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GameplaySettings {
     pub wagon_speed: f32,
     pub node_wagon_spawn_time: f32,
+    pub days_per_second: f32,
+}
+
+impl Default for GameplaySettings {
+    fn default() -> Self {
+        Self {
+            wagon_speed: 1.0,
+            node_wagon_spawn_time: 15.0,
+            days_per_second: 1.0,
+        }
+    }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SaveSettings {
     pub save_directory: Box<Path>,
+    /// Seconds between autosaves.
     pub save_interval: f32,
-}
\ No newline at end of file
+
+    /// How many autosaves to keep before the oldest is overwritten.
+    pub autosave_slots: u32,
+}
+
+impl Default for SaveSettings {
+    fn default() -> Self {
+        Self {
+            save_directory: Path::new("./saves").into(),
+            save_interval: 300.0,
+            autosave_slots: 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+
+    /// A command line override couldn't be applied.
+    BadOverride {
+        key: String,
+        reason: String,
+    },
+
+    /// The settings were read but some values don't make sense.
+    Invalid(Vec<String>),
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Parse(err) => write!(f, "invalid settings file: {}", err),
+            Self::BadOverride { key, reason } => {
+                write!(f, "can't override setting `{}`: {}", key, reason)
+            }
+            Self::Invalid(problems) => {
+                write!(f, "invalid settings:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<io::Error> for SettingsError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ron::error::SpannedError> for SettingsError {
+    fn from(value: ron::error::SpannedError) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl Settings {
+    /// Read settings from a RON file. This doesn't validate them.
+    pub fn load(path: &Path) -> Result<Settings, SettingsError> {
+        let text = fs::read_to_string(path)?;
+        Ok(ron::from_str(&text)?)
+    }
+
+    /// Override a single setting, given as a dotted path such as `map_gen.city_num`.
+    /// The value is parsed as JSON, falling back to a plain string.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), SettingsError> {
+        let bad_override = |reason: String| SettingsError::BadOverride {
+            key: key.to_string(),
+            reason,
+        };
+
+        let mut tree = serde_json::to_value(&*self).map_err(|err| bad_override(err.to_string()))?;
+        let mut field = &mut tree;
+        for part in key.split('.') {
+            field = field
+                .as_object_mut()
+                .and_then(|object| object.get_mut(part))
+                .ok_or_else(|| bad_override("no such setting".to_string()))?;
+        }
+        *field = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+
+        *self = serde_json::from_value(tree).map_err(|err| bad_override(err.to_string()))?;
+        Ok(())
+    }
+
+    /// Check that every setting has a usable value, reporting all the problems found.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        let map_gen = &self.map_gen;
+        check(
+            map_gen.size.x > 0.0 && map_gen.size.y > 0.0,
+            "map_gen.size must be positive",
+        );
+        check(
+            map_gen.sector_num > 0,
+            "map_gen.sector_num must be at least 1",
+        );
+        check(
+            map_gen.site_min_spacing > 0.0,
+            "map_gen.site_min_spacing must be positive",
+        );
+        check(
+            map_gen.generator_border >= 0.0,
+            "map_gen.generator_border can't be negative",
+        );
+        check(
+            map_gen.altitude_perlin_scale > 0.0,
+            "map_gen.altitude_perlin_scale must be positive",
+        );
+        check(
+            (-1.0..=1.0).contains(&map_gen.water_cutoff),
+            "map_gen.water_cutoff must be between -1 and 1",
+        );
+        for (name, image) in [
+            ("heightmap", &map_gen.heightmap),
+            ("biome_map", &map_gen.biome_map),
+        ] {
+            if let Some(path) = image {
+                check(
+                    path.is_file(),
+                    &format!("map_gen.{} {} doesn't exist", name, path.display()),
+                );
+            }
+        }
+        check(
+            map_gen.shape_strength >= 0.0,
+            "map_gen.shape_strength can't be negative",
+        );
+        check(
+            (0.0..=1.0).contains(&map_gen.erosion_rate),
+            "map_gen.erosion_rate must be between 0 and 1",
+        );
+        check(
+            map_gen.sediment_capacity >= 0.0,
+            "map_gen.sediment_capacity can't be negative",
+        );
+        check(
+            (0.0..=1.0).contains(&map_gen.deposition_rate),
+            "map_gen.deposition_rate must be between 0 and 1",
+        );
+        check(
+            map_gen.talus_slope >= 0.0,
+            "map_gen.talus_slope can't be negative",
+        );
+        check(
+            (0.0..=1.0).contains(&map_gen.thermal_rate),
+            "map_gen.thermal_rate must be between 0 and 1",
+        );
+        check(
+            map_gen.slope_cost >= 0.0,
+            "map_gen.slope_cost can't be negative",
+        );
+        check(
+            map_gen.biome_seed_num > 0,
+            "map_gen.biome_seed_num must be at least 1",
+        );
+        check(
+            map_gen.moisture_falloff > 0.0,
+            "map_gen.moisture_falloff must be positive",
+        );
+        check(
+            map_gen.wind_direction != Vec2::ZERO,
+            "map_gen.wind_direction can't be zero",
+        );
+        check(
+            map_gen.rain_shadow >= 0.0,
+            "map_gen.rain_shadow can't be negative",
+        );
+        check(
+            map_gen.river_cost >= 0.0,
+            "map_gen.river_cost can't be negative",
+        );
+        check(
+            map_gen.city_min_spacing >= 0.0,
+            "map_gen.city_min_spacing can't be negative",
+        );
+        check(
+            !map_gen.city_start_pop_range.is_empty(),
+            "map_gen.city_start_pop_range is empty",
+        );
+        check(
+            map_gen.city_deadzone >= 0.0,
+            "map_gen.city_deadzone can't be negative",
+        );
+        check(
+            !map_gen.nodes_per_city_range.is_empty(),
+            "map_gen.nodes_per_city_range is empty",
+        );
+        check(
+            map_gen.node_city_min_dist >= 0.0,
+            "map_gen.node_city_min_dist can't be negative",
+        );
+        check(
+            map_gen.node_city_min_dist <= map_gen.node_city_max_dist,
+            "map_gen.node_city_min_dist is greater than map_gen.node_city_max_dist",
+        );
+        check(
+            map_gen.node_min_spacing >= 0.0,
+            "map_gen.node_min_spacing can't be negative",
+        );
+        check(
+            map_gen.node_deadzone >= 0.0,
+            "map_gen.node_deadzone can't be negative",
+        );
+        check(
+            map_gen.province_num > 0,
+            "map_gen.province_num must be at least 1",
+        );
+
+        check(
+            self.gameplay.wagon_speed > 0.0,
+            "gameplay.wagon_speed must be positive",
+        );
+        check(
+            self.gameplay.node_wagon_spawn_time > 0.0,
+            "gameplay.node_wagon_spawn_time must be positive",
+        );
+        check(
+            self.gameplay.days_per_second >= 0.0,
+            "gameplay.days_per_second can't be negative",
+        );
+
+        check(
+            (0.0..=1.0).contains(&self.display.hillshade_strength),
+            "display.hillshade_strength must be between 0 and 1",
+        );
+        check(
+            self.display.relief_exaggeration >= 0.0,
+            "display.relief_exaggeration can't be negative",
+        );
+        check(
+            self.display.map_chunk_size > 0.0,
+            "display.map_chunk_size must be positive",
+        );
+
+        check(
+            self.display.road_width > 0.0,
+            "display.road_width must be positive",
+        );
+
+        check(
+            self.display.river_width > 0.0,
+            "display.river_width must be positive",
+        );
+
+        check(
+            self.display.province_border_width > 0.0,
+            "display.province_border_width must be positive",
+        );
+
+        check(
+            self.save.save_interval > 0.0,
+            "save.save_interval must be positive",
+        );
+        check(
+            self.save.autosave_slots > 0,
+            "save.autosave_slots must be at least 1",
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(SettingsError::Invalid(problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_settings_are_valid() {
+        Settings::default().validate().unwrap();
+        for path in ["assets/settings.ron", "assets/presets/mediterranean.ron"] {
+            Settings::load(Path::new(path)).unwrap().validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn overrides_set_nested_values() {
+        let mut settings = Settings::default();
+        settings.set("map_gen.city_num", "20").unwrap();
+        settings
+            .set("map_gen.city_start_pop_range", r#"{"start":1,"end":3}"#)
+            .unwrap();
+        settings.set("save.save_directory", "elsewhere").unwrap();
+
+        assert_eq!(settings.map_gen.city_num, 20);
+        assert_eq!(settings.map_gen.city_start_pop_range, 1..3);
+        assert_eq!(&*settings.save.save_directory, Path::new("elsewhere"));
+    }
+
+    #[test]
+    fn bad_overrides_are_rejected() {
+        let mut settings = Settings::default();
+        for (key, value) in [
+            ("map_gen.no_such_setting", "1"),
+            ("map_gen", "1"),
+            ("map_gen.city_num", "-1"),
+            ("map_gen.city_num", "many"),
+        ] {
+            assert!(matches!(
+                settings.set(key, value),
+                Err(SettingsError::BadOverride { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let mut settings = Settings::default();
+        settings.set("map_gen.sector_num", "0").unwrap();
+        settings.set("save.save_interval", "0").unwrap();
+
+        match settings.validate() {
+            Err(SettingsError::Invalid(problems)) => assert_eq!(problems.len(), 2),
+            _ => panic!("invalid settings passed validation"),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    biome::BiomeRegistry,
+    map::Sector,
+    settings::{DisplaySettings, MapGenSettings},
+};
+
+/// How the map mesh is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MapShading {
+    /// Every sector is one flat biome color.
+    #[default]
+    Flat,
+
+    /// Biome colors are lightened with height, darkened with depth and lit by a hillshade,
+    /// then blended between neighboring sectors so the map reads like a relief map.
+    /// Colors are never blended across a coast, so coastlines stay sharp.
+    Relief,
+}
+
+/// The direction light shines from in [`MapShading::Relief`]: from the northwest
+/// and high above, as is usual for shaded relief maps.
+const LIGHT_DIRECTION: Vec3 = vec3(-1.0, 1.0, 1.5);
+
+/// Colors sectors in the map mesh.
+/// Heights are tinted relative to the whole map, so build this once from every sector.
+#[derive(Resource, Debug)]
+pub struct MapPalette {
+    shading: MapShading,
+    hillshade_strength: f32,
+    relief_exaggeration: f32,
+    water_cutoff: f32,
+
+    /// The highest and lowest heights on the map.
+    highest: f32,
+    lowest: f32,
+}
+
+impl MapPalette {
+    pub fn new(
+        sector_query: &Query<&Sector>,
+        display_settings: &DisplaySettings,
+        map_gen_settings: &MapGenSettings,
+    ) -> Self {
+        let (lowest, highest) = sector_query.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY),
+            |(lowest, highest), sector| (lowest.min(sector.height), highest.max(sector.height)),
+        );
+
+        Self {
+            shading: display_settings.map_shading,
+            hillshade_strength: display_settings.hillshade_strength,
+            relief_exaggeration: display_settings.relief_exaggeration,
+            water_cutoff: map_gen_settings.water_cutoff,
+            highest,
+            lowest,
+        }
+    }
+
+    /// The color of each point in a sector's border, in the same order as the border.
+    pub fn border_colors(
+        &self,
+        sector: &Sector,
+        sector_query: &Query<&Sector>,
+        biomes: &BiomeRegistry,
+    ) -> Vec<LinearRgba> {
+        let color = self.sector_color(sector, sector_query, biomes);
+        if self.shading == MapShading::Flat {
+            return vec![color; sector.border.len()];
+        }
+
+        // Each corner is the average color of the sectors meeting there,
+        // leaving out any on the other side of a coast
+        let is_water = sector.water_body.is_some();
+        let blended: Vec<(&Sector, Vec3)> = sector
+            .neighbors
+            .iter()
+            .map(|e_neighbor| sector_query.get(*e_neighbor).unwrap())
+            .filter(|neighbor| neighbor.water_body.is_some() == is_water)
+            .map(|neighbor| {
+                let color = self.sector_color(neighbor, sector_query, biomes);
+                (neighbor, color.to_vec3())
+            })
+            .collect();
+
+        sector
+            .border
+            .iter()
+            .map(|corner| {
+                let (total, count) = blended
+                    .iter()
+                    .filter(|(neighbor, _)| neighbor.border.contains(corner))
+                    .fold((color.to_vec3(), 1.0f32), |(total, count), (_, color)| {
+                        (total + *color, count + 1.0)
+                    });
+                LinearRgba::from_vec3(total / count)
+            })
+            .collect()
+    }
+
+    /// The color of a whole sector before it is blended with its neighbors.
+    fn sector_color(
+        &self,
+        sector: &Sector,
+        sector_query: &Query<&Sector>,
+        biomes: &BiomeRegistry,
+    ) -> LinearRgba {
+        let (r, g, b) = biomes
+            .get(sector.biome.unwrap())
+            .map_color(sector.water_body);
+        let color = Color::srgb_u8(r, g, b).to_linear();
+        if self.shading == MapShading::Flat {
+            return color;
+        }
+
+        let brightness = if sector.water_body.is_some() {
+            // Deep water is darker, so the shallows along the coast stand out
+            let depth = fraction(sector.height, self.water_cutoff, self.lowest);
+            1.0 - 0.4 * depth
+        } else {
+            let elevation = fraction(sector.height, self.water_cutoff, self.highest);
+            (0.85 + 0.3 * elevation) * self.hillshade(sector, sector_query)
+        };
+
+        LinearRgba::from_vec3(color.to_vec3() * brightness)
+    }
+
+    /// How brightly a sector is lit compared to flat ground, from the slope of a plane
+    /// fitted through its height and its neighbors' heights.
+    fn hillshade(&self, sector: &Sector, sector_query: &Query<&Sector>) -> f32 {
+        // Least squares fit of the height gradient from the differences to each neighbor
+        let (mut xx, mut xy, mut yy, mut xh, mut yh) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for e_neighbor in &sector.neighbors {
+            let neighbor = sector_query.get(*e_neighbor).unwrap();
+            let offset = neighbor.centroid - sector.centroid;
+            let rise = neighbor.height - sector.height;
+            xx += offset.x * offset.x;
+            xy += offset.x * offset.y;
+            yy += offset.y * offset.y;
+            xh += offset.x * rise;
+            yh += offset.y * rise;
+        }
+        let determinant = xx * yy - xy * xy;
+        if determinant.abs() < f32::EPSILON {
+            return 1.0;
+        }
+        let gradient = vec2(yy * xh - xy * yh, xx * yh - xy * xh) / determinant;
+
+        let normal = (-gradient * self.relief_exaggeration)
+            .extend(1.0)
+            .normalize();
+        let light = LIGHT_DIRECTION.normalize();
+        let lit = normal.dot(light).max(0.0) / light.z;
+        1.0 + (lit - 1.0) * self.hillshade_strength
+    }
+}
+
+/// How far a value is from `start` towards `end`, from 0 to 1.
+fn fraction(value: f32, start: f32, end: f32) -> f32 {
+    if start == end {
+        return 0.0;
+    }
+    ((value - start) / (end - start)).clamp(0.0, 1.0)
+}
@@ -0,0 +1,111 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The overall layout of land and sea, applied as a mask on top of the height noise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MapShape {
+    /// Noise only, so land can run off every edge of the map.
+    #[default]
+    Noise,
+
+    /// One island in the middle of the map, falling away radially to the sea.
+    Island,
+
+    /// Many small islands scattered across the map, with open sea around the edges.
+    Archipelago,
+
+    /// A single landmass filling the map, with a sea enclosed in the middle.
+    Continent,
+
+    /// A long sea across the middle of the map from west to east,
+    /// with land to the north and south, like the Mediterranean.
+    Mediterranean,
+}
+
+impl MapShape {
+    /// How much to raise (positive) or sink (negative) the land at a point,
+    /// given as a fraction of the map size from 0 to 1 on each axis.
+    /// `detail` is higher frequency noise from -1 to 1 for shapes that need it.
+    pub fn mask(self, position: Vec2, detail: f32) -> f32 {
+        // From -1 to 1 on each axis, with 0 in the middle of the map
+        let centered = (position - 0.5) * 2.0;
+        let from_center = centered.length();
+
+        // 0 over most of the map, rising steeply to 1 at the edges
+        let edge = centered.abs().max_element().powi(4);
+
+        match self {
+            Self::Noise => 0.0,
+            Self::Island => 1.0 - 2.0 * from_center,
+            Self::Archipelago => 1.5 * detail - 0.4 - 1.5 * edge,
+            Self::Continent => {
+                let basin = (-(from_center / 0.3).powi(2)).exp();
+                0.8 - 1.8 * edge - 2.0 * basin
+            }
+            Self::Mediterranean => {
+                // The sea is widest in the middle and narrows to a strait at the western edge
+                // and a closed shore at the eastern edge.
+                let x = position.x;
+                let half_width = 0.35 * (x * PI).sin().max(0.0).sqrt() + 0.05 * (1.0 - x);
+                let sea = if half_width > 0.0 {
+                    1.0 - (centered.y.abs() / half_width).min(1.0)
+                } else {
+                    0.0
+                };
+                0.6 - 2.0 * sea
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points along one edge of the map, as fractions of the map size.
+    fn edge(from: Vec2, to: Vec2) -> impl Iterator<Item = Vec2> {
+        (0..=100).map(move |i| from.lerp(to, i as f32 / 100.0))
+    }
+
+    fn every_edge() -> impl Iterator<Item = Vec2> {
+        edge(vec2(0.0, 0.0), vec2(1.0, 0.0))
+            .chain(edge(vec2(0.0, 1.0), vec2(1.0, 1.0)))
+            .chain(edge(vec2(0.0, 0.0), vec2(0.0, 1.0)))
+            .chain(edge(vec2(1.0, 0.0), vec2(1.0, 1.0)))
+    }
+
+    #[test]
+    fn islands_and_continents_sink_at_the_edges() {
+        for shape in [MapShape::Island, MapShape::Continent] {
+            for point in every_edge() {
+                let mask = shape.mask(point, 0.0);
+                assert!(mask <= -0.99, "{:?} is {} at {}", shape, mask, point);
+            }
+        }
+
+        assert!(MapShape::Island.mask(vec2(0.5, 0.5), 0.0) > 0.9);
+
+        // The continent rises between its coast and the inland sea in the middle
+        assert!(MapShape::Continent.mask(vec2(0.5, 0.2), 0.0) > 0.0);
+        assert!(MapShape::Continent.mask(vec2(0.5, 0.5), 0.0) < 0.0);
+    }
+
+    #[test]
+    fn the_mediterranean_only_opens_to_the_west() {
+        let shape = MapShape::Mediterranean;
+        let closed = edge(vec2(0.0, 0.0), vec2(1.0, 0.0))
+            .chain(edge(vec2(0.0, 1.0), vec2(1.0, 1.0)))
+            .chain(edge(vec2(1.0, 0.0), vec2(1.0, 1.0)));
+        for point in closed {
+            let mask = shape.mask(point, 0.0);
+            assert!(mask > 0.0, "the shore is open at {} ({})", point, mask);
+        }
+
+        // The strait sinks the land on the west edge by as much as the middle of the sea
+        assert!(shape.mask(vec2(0.0, 0.5), 0.0) < -1.0);
+        assert!(shape.mask(vec2(0.5, 0.5), 0.0) < -1.0);
+        assert!(shape.mask(vec2(0.0, 0.2), 0.0) > 0.0);
+    }
+}
@@ -1,9 +1,12 @@
+use std::collections::BTreeMap;
+
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::resource::Resource;
 
 /// Struct carrying data about a shipment.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Shipment {
     pub resource: Resource,
     pub quantity: u32,
@@ -13,6 +16,9 @@ pub struct Shipment {
 #[derive(Component)]
 pub struct ShipmentReceiver {
     incoming: Vec<Shipment>,
+
+    /// Total quantity of each resource ever received.
+    received: BTreeMap<Resource, u32>,
 }
 
 impl Default for ShipmentReceiver {
@@ -25,6 +31,15 @@ impl ShipmentReceiver {
     pub fn new() -> ShipmentReceiver {
         ShipmentReceiver {
             incoming: Vec::new(),
+            received: BTreeMap::new(),
+        }
+    }
+
+    /// A receiver that has already been sent these totals, such as one restored from a save.
+    pub fn with_received(received: BTreeMap<Resource, u32>) -> ShipmentReceiver {
+        ShipmentReceiver {
+            incoming: Vec::new(),
+            received,
         }
     }
 
@@ -38,8 +53,14 @@ impl ShipmentReceiver {
         self.incoming.pop()
     }
 
+    /// Return the total quantity of each resource this receiver has been sent.
+    pub fn total_received(&self) -> &BTreeMap<Resource, u32> {
+        &self.received
+    }
+
     /// Add a shipment to this receiver
     pub fn add_shipment(&mut self, shipment: Shipment) {
+        *self.received.entry(shipment.resource).or_default() += shipment.quantity;
         self.incoming.push(shipment);
     }
 }
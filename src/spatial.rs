@@ -0,0 +1,250 @@
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::{city::City, map::Sector, settings::MapGenSettings};
+
+/// Points bucketed into a uniform grid of square cells, for fast neighborhood queries.
+/// Queries visit cells in a fixed order, so their results don't depend on hashing.
+#[derive(Debug, Clone)]
+pub struct PointGrid<T> {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<(Vec2, T)>>,
+
+    /// The corners of the smallest block of cells holding every point.
+    min_cell: IVec2,
+    max_cell: IVec2,
+}
+
+impl<T: Copy> PointGrid<T> {
+    /// An empty grid. Cells about as wide as the typical query radius work best.
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "grid cells must have a positive size");
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            min_cell: IVec2::MAX,
+            max_cell: IVec2::MIN,
+        }
+    }
+
+    pub fn insert(&mut self, point: Vec2, value: T) {
+        let cell = self.cell_of(point);
+        self.min_cell = self.min_cell.min(cell);
+        self.max_cell = self.max_cell.max(cell);
+        self.cells.entry(cell).or_default().push((point, value));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Every value whose point is strictly closer than `radius` to `point`.
+    pub fn within_radius(&self, point: Vec2, radius: f32) -> impl Iterator<Item = T> + '_ {
+        let min = self.cell_of(point - radius).max(self.min_cell);
+        let max = self.cell_of(point + radius).min(self.max_cell);
+
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| ivec2(x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .filter(move |(other, _)| other.distance(point) < radius)
+            .map(|(_, value)| *value)
+    }
+
+    /// The value whose point is closest to `point`, and where that point is,
+    /// or `None` if the grid is empty.
+    pub fn nearest(&self, point: Vec2) -> Option<(T, Vec2)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // Search rings of cells outwards from the point. Anything in ring r + 1 is at least
+        // r cells away, so once the best match is closer than that the search can stop.
+        let center = self.cell_of(point);
+        let last_ring = (center - self.min_cell)
+            .abs()
+            .max((self.max_cell - center).abs())
+            .max_element();
+        let mut best: Option<(T, Vec2, f32)> = None;
+        for ring in 0..=last_ring {
+            for cell in ring_cells(center, ring) {
+                for (other, value) in self.cells.get(&cell).into_iter().flatten() {
+                    let distance = other.distance(point);
+                    if best.is_none_or(|(_, _, best_distance)| distance < best_distance) {
+                        best = Some((*value, *other, distance));
+                    }
+                }
+            }
+
+            if best.is_some_and(|(_, _, distance)| distance <= ring as f32 * self.cell_size) {
+                break;
+            }
+        }
+
+        best.map(|(value, other, _)| (value, other))
+    }
+
+    fn cell_of(&self, point: Vec2) -> IVec2 {
+        (point / self.cell_size).floor().as_ivec2()
+    }
+}
+
+/// The cells exactly `ring` cells away from `center`, in a fixed order.
+fn ring_cells(center: IVec2, ring: i32) -> impl Iterator<Item = IVec2> {
+    (-ring..=ring).flat_map(move |y| {
+        (-ring..=ring)
+            .filter(move |x| x.abs() == ring || y.abs() == ring)
+            .map(move |x| center + ivec2(x, y))
+    })
+}
+
+/// Answers "what is near this point?" for sectors and cities.
+/// Built once the world has been generated or loaded.
+#[derive(Resource, Debug)]
+pub struct SpatialIndex {
+    pub sectors: PointGrid<Entity>,
+    pub cities: PointGrid<Entity>,
+
+    /// The size of the map, outside of which there are no sectors.
+    size: Vec2,
+}
+
+impl SpatialIndex {
+    /// The sector under a point, or `None` if the point is off the map.
+    /// Sectors are voronoi cells, so this is the sector with the nearest site.
+    pub fn sector_at(&self, point: Vec2) -> Option<Entity> {
+        if point.cmplt(Vec2::ZERO).any() || point.cmpgt(self.size).any() {
+            return None;
+        }
+        self.sectors.nearest(point).map(|(sector, _)| sector)
+    }
+
+    pub fn nearest_city(&self, point: Vec2) -> Option<Entity> {
+        self.cities.nearest(point).map(|(city, _)| city)
+    }
+
+    /// Every city strictly closer than `radius` to `point`.
+    pub fn within_radius(&self, point: Vec2, radius: f32) -> impl Iterator<Item = Entity> + '_ {
+        self.cities.within_radius(point, radius)
+    }
+}
+
+/// Index every sector and city in the world.
+pub fn build_spatial_index(
+    mut commands: Commands,
+    sector_query: Query<(Entity, &Sector)>,
+    city_query: Query<(Entity, &Transform), With<City>>,
+    settings: Res<MapGenSettings>,
+) {
+    // About one sector per cell
+    let sector_cell = (settings.size.x * settings.size.y / settings.sector_num as f32).sqrt();
+    let mut sectors = PointGrid::new(sector_cell.max(1.0));
+    for (e_sector, sector) in sector_query {
+        sectors.insert(sector.site, e_sector);
+    }
+
+    let mut cities = PointGrid::new(settings.city_min_spacing.max(1.0));
+    for (e_city, t_city) in city_query {
+        cities.insert(t_city.translation.xy(), e_city);
+    }
+
+    commands.insert_resource(SpatialIndex {
+        sectors,
+        cities,
+        size: settings.size,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_prng::WyRand;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// A grid of random points on a 100 by 50 map, along with the points themselves.
+    fn random_grid(
+        rng: &mut WyRand,
+        count: usize,
+        cell_size: f32,
+    ) -> (PointGrid<usize>, Vec<Vec2>) {
+        let points: Vec<Vec2> = (0..count)
+            .map(|_| vec2(rng.random_range(0.0..100.0), rng.random_range(0.0..50.0)))
+            .collect();
+        let mut grid = PointGrid::new(cell_size);
+        for (i, point) in points.iter().enumerate() {
+            grid.insert(*point, i);
+        }
+        (grid, points)
+    }
+
+    /// A query point that can land well off the map on any side.
+    fn random_query(rng: &mut WyRand) -> Vec2 {
+        vec2(
+            rng.random_range(-60.0..160.0),
+            rng.random_range(-60.0..110.0),
+        )
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let mut rng = WyRand::seed_from_u64(1);
+        for cell_size in [0.5, 3.0, 40.0] {
+            let (grid, points) = random_grid(&mut rng, 300, cell_size);
+            for _ in 0..500 {
+                let query = random_query(&mut rng);
+                let closest = points
+                    .iter()
+                    .map(|point| point.distance(query))
+                    .fold(f32::INFINITY, f32::min);
+
+                // Compared by distance, as two points can be equally close
+                let (i, point) = grid.nearest(query).unwrap();
+                assert_eq!(points[i], point);
+                assert_eq!(point.distance(query), closest);
+            }
+        }
+    }
+
+    #[test]
+    fn within_radius_matches_brute_force() {
+        let mut rng = WyRand::seed_from_u64(2);
+        for cell_size in [0.5, 3.0, 40.0] {
+            let (grid, points) = random_grid(&mut rng, 300, cell_size);
+            for _ in 0..500 {
+                let query = random_query(&mut rng);
+                let radius = rng.random_range(0.0..30.0);
+                let mut found: Vec<usize> = grid.within_radius(query, radius).collect();
+                found.sort();
+                let expected: Vec<usize> = (0..points.len())
+                    .filter(|i| points[*i].distance(query) < radius)
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_grids_find_nothing() {
+        let grid: PointGrid<usize> = PointGrid::new(1.0);
+        assert!(grid.nearest(vec2(5.0, 5.0)).is_none());
+        assert_eq!(grid.within_radius(vec2(5.0, 5.0), 100.0).count(), 0);
+    }
+
+    #[test]
+    fn points_off_the_map_have_no_sector() {
+        let mut sectors = PointGrid::new(10.0);
+        sectors.insert(vec2(5.0, 5.0), Entity::from_raw(0));
+        sectors.insert(vec2(95.0, 45.0), Entity::from_raw(1));
+        let index = SpatialIndex {
+            sectors,
+            cities: PointGrid::new(10.0),
+            size: vec2(100.0, 50.0),
+        };
+
+        assert_eq!(index.sector_at(vec2(20.0, 10.0)), Some(Entity::from_raw(0)));
+        assert_eq!(index.sector_at(vec2(80.0, 40.0)), Some(Entity::from_raw(1)));
+        assert_eq!(index.sector_at(vec2(-1.0, 10.0)), None);
+        assert_eq!(index.sector_at(vec2(20.0, 51.0)), None);
+        assert_eq!(index.nearest_city(vec2(20.0, 10.0)), None);
+    }
+}
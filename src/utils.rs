@@ -1,13 +1,16 @@
-use std::collections::{BinaryHeap, HashMap};
+use std::{
+    collections::{BinaryHeap, HashMap},
+    hash::Hash,
+};
 
+#[cfg(feature = "render")]
 use bevy::{
     asset::RenderAssetUsages,
-    ecs::query::QueryEntityError,
-    prelude::*,
     render::mesh::{Indices, PrimitiveTopology},
 };
+use bevy::{ecs::query::QueryEntityError, prelude::*};
 
-use crate::map::Sector;
+use crate::{map::Sector, worldgen::WorldSector};
 
 /// Compute the centroid of a polygon.
 pub fn centroid(vertices: &Vec<Vec2>) -> Vec2 {
@@ -46,47 +49,76 @@ impl From<QueryEntityError> for PathfindingError {
     }
 }
 
+/// A graph of sectors that roads can be pathfound across.
+pub trait SectorGraph {
+    /// The type used to refer to a sector in this graph.
+    type Id: Copy + Eq + Hash;
+
+    /// Get the centroid, traversal cost and neighbors of a sector.
+    fn sector_info(&self, id: Self::Id) -> Option<(Vec2, f32, &[Self::Id])>;
+}
+
+impl SectorGraph for Query<'_, '_, &Sector> {
+    type Id = Entity;
+
+    fn sector_info(&self, id: Entity) -> Option<(Vec2, f32, &[Entity])> {
+        let sector = self.get(id).ok()?;
+        Some((sector.centroid, sector.cost, &sector.neighbors))
+    }
+}
+
+impl SectorGraph for [WorldSector] {
+    type Id = usize;
+
+    fn sector_info(&self, id: usize) -> Option<(Vec2, f32, &[usize])> {
+        let sector = self.get(id)?;
+        Some((sector.centroid, sector.cost, &sector.neighbors))
+    }
+}
+
 /// Return a path of sectors from the start to the end.
 /// The returned path includes both the start and the end.
 /// Uses A* algorithm for pathfinding (working backwards).
-pub fn pathfind(
-    start: Entity,
-    end: Entity,
-    sector_query: &Query<&Sector>,
-) -> Result<Vec<Entity>, PathfindingError> {
-    let start_point = sector_query.get(start)?.centroid;
-    let heuristic = |e_sector: Entity| -> Result<f32, PathfindingError> {
-        let sector = sector_query.get(e_sector)?;
-        Ok(start_point.distance(sector.centroid))
+pub fn pathfind<G: SectorGraph + ?Sized>(
+    start: G::Id,
+    end: G::Id,
+    graph: &G,
+) -> Result<Vec<G::Id>, PathfindingError> {
+    let lookup = |id: G::Id| {
+        graph
+            .sector_info(id)
+            .ok_or(PathfindingError::EntityIsNotSector)
     };
 
-    let actual_cost = |sector0: &Sector, sector1: &Sector| -> f32 {
-        let dist = sector0.centroid.distance(sector1.centroid);
-        0.5 * dist * (sector0.cost + sector1.cost)
+    let (start_point, _, _) = lookup(start)?;
+    let heuristic = |centroid: Vec2| -> f32 { start_point.distance(centroid) };
+
+    let actual_cost = |centroid0: Vec2, cost0: f32, centroid1: Vec2, cost1: f32| -> f32 {
+        let dist = centroid0.distance(centroid1);
+        0.5 * dist * (cost0 + cost1)
     };
 
     // Maps sectors we've searched to the next sector in the path.
-    let mut previous: HashMap<Entity, Entity> = HashMap::new();
+    let mut previous: HashMap<G::Id, G::Id> = HashMap::new();
 
     // Maps sectors we've searched to the cost to get to them.
-    let mut costs_so_far: HashMap<Entity, f32> = HashMap::new();
+    let mut costs_so_far: HashMap<G::Id, f32> = HashMap::new();
     costs_so_far.insert(end, 0.0);
 
     // The current sectors to search, ordered by the heuristic cost estimate
     // for a path to the start starting from there.
-    let mut border: BinaryHeap<BorderSorter> = BinaryHeap::new();
+    let mut border: BinaryHeap<BorderSorter<G::Id>> = BinaryHeap::new();
     border.push(BorderSorter {
-        estimate: heuristic(end)?,
-        e_sector: end,
+        estimate: heuristic(lookup(end)?.0),
+        sector: end,
     });
 
-    while !border.is_empty() {
-        let BorderSorter {
-            estimate: _,
-            e_sector,
-        } = border.pop().unwrap();
-
-        if e_sector == start {
+    while let Some(BorderSorter {
+        estimate: _,
+        sector: current,
+    }) = border.pop()
+    {
+        if current == start {
             let mut path = vec![start];
             let mut curr = start;
 
@@ -98,24 +130,25 @@ pub fn pathfind(
             return Ok(path);
         }
 
-        let sector = sector_query.get(e_sector)?;
-        let cost = *costs_so_far.get(&e_sector).unwrap();
+        let (centroid, sector_cost, neighbors) = lookup(current)?;
+        let cost = *costs_so_far.get(&current).unwrap();
 
-        for neighbor in &sector.neighbors {
-            let neighbor_sector = sector_query.get(*neighbor)?;
+        for neighbor in neighbors {
+            let (neighbor_centroid, neighbor_cost, _) = lookup(*neighbor)?;
 
             // The cost to get to the neighbor through the current sector
-            let new_cost = cost + actual_cost(sector, neighbor_sector);
+            let new_cost =
+                cost + actual_cost(centroid, sector_cost, neighbor_centroid, neighbor_cost);
 
             // The previous best cost to the neighbor (or infinity if none found yet)
             let old_cost = *costs_so_far.get(neighbor).unwrap_or(&f32::INFINITY);
 
             if new_cost < old_cost {
-                previous.insert(*neighbor, e_sector);
+                previous.insert(*neighbor, current);
                 costs_so_far.insert(*neighbor, new_cost);
                 border.push(BorderSorter {
-                    estimate: new_cost + heuristic(*neighbor)?,
-                    e_sector: *neighbor,
+                    estimate: new_cost + heuristic(neighbor_centroid),
+                    sector: *neighbor,
                 });
             }
         }
@@ -125,48 +158,49 @@ pub fn pathfind(
 }
 
 /// Get a road-shaped path between sectors as both a curve and a list of sectors.
-pub fn bezier_pathfind(
-    start: Entity,
-    end: Entity,
-    sector_query: &Query<&Sector>,
-) -> Result<(CubicCurve<Vec2>, Vec<Entity>), PathfindingError> {
-    let path = pathfind(start, end, sector_query)?;
+pub fn bezier_pathfind<G: SectorGraph + ?Sized>(
+    start: G::Id,
+    end: G::Id,
+    graph: &G,
+) -> Result<(CubicCurve<Vec2>, Vec<G::Id>), PathfindingError> {
+    let path = pathfind(start, end, graph)?;
     Ok((
         bezier_curve(
             path.iter()
-                .map(|e| sector_query.get(*e).unwrap().centroid)
+                .map(|id| graph.sector_info(*id).unwrap().0)
                 .collect(),
         ),
         path,
     ))
 }
 
-struct BorderSorter {
+struct BorderSorter<Id> {
     estimate: f32,
-    e_sector: Entity,
+    sector: Id,
 }
 
-impl Ord for BorderSorter {
+impl<Id: Eq> Ord for BorderSorter<Id> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         other.estimate.total_cmp(&self.estimate)
     }
 }
 
-impl PartialOrd for BorderSorter {
+impl<Id: Eq> PartialOrd for BorderSorter<Id> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl PartialEq for BorderSorter {
+impl<Id: Eq> PartialEq for BorderSorter<Id> {
     fn eq(&self, other: &Self) -> bool {
-        self.e_sector == other.e_sector
+        self.sector == other.sector
     }
 }
 
-impl Eq for BorderSorter {}
+impl<Id: Eq> Eq for BorderSorter<Id> {}
 
 /// Creates a line mesh from a set of points
+#[cfg(feature = "render")]
 pub fn line_mesh(path: &Vec<Vec2>, width: f32) -> Mesh {
     let point_neighbors = |point: Vec2, facing: Vec2| -> (Vec2, Vec2) {
         let perp = facing.perp().normalize() * width;
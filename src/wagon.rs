@@ -1,8 +1,9 @@
-use bevy::prelude::*;
+use bevy::{platform::collections::HashMap, prelude::*};
 
+#[cfg(feature = "render")]
+use crate::resource::Resource;
 use crate::{
     click_off::Despawning,
-    resource::Resource,
     road::Road,
     settings::GameplaySettings,
     shipment::{Shipment, ShipmentReceiver},
@@ -12,10 +13,10 @@ use crate::{
 #[derive(Component)]
 #[require(Transform)]
 pub struct Wagon {
-    shipment: Shipment,
-    road: Entity,
-    distance: f32,
-    destination: Entity,
+    pub shipment: Shipment,
+    pub road: Entity,
+    pub distance: f32,
+    pub destination: Entity,
 }
 
 impl Wagon {
@@ -29,6 +30,26 @@ impl Wagon {
     }
 }
 
+/// How many wagons have set off through each sector since the game was started or loaded.
+#[derive(Resource, Debug, Default)]
+pub struct SectorTraffic(pub HashMap<Entity, u32>);
+
+/// Count every new wagon towards the traffic of each sector along its road.
+pub fn record_traffic(
+    wagon_query: Query<&Wagon, Added<Wagon>>,
+    road_query: Query<&Road>,
+    mut traffic: ResMut<SectorTraffic>,
+) {
+    for wagon in wagon_query {
+        let Ok(road) = road_query.get(wagon.road) else {
+            continue;
+        };
+        for e_sector in &road.path {
+            *traffic.0.entry(*e_sector).or_default() += 1;
+        }
+    }
+}
+
 pub fn move_wagons(
     time: Res<Time>,
     settings: Res<GameplaySettings>,
@@ -62,6 +83,7 @@ pub fn move_wagons(
     }
 }
 
+#[cfg(feature = "render")]
 pub fn add_wagon_meshes(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{biome::BiomeRegistry, settings::MapGenSettings, worldgen::WorldSector};
+
+/// The kind of body of water a water sector belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WaterBody {
+    /// Water connected to the edge of the map.
+    Ocean,
+
+    /// Enclosed water with at least `inland_sea_min_size` sectors.
+    InlandSea,
+
+    /// Any smaller enclosed water.
+    Lake,
+}
+
+/// Label every water sector with the body of water it belongs to,
+/// and mark land sectors next to any water as coastal.
+pub fn classify_water(
+    sectors: &mut [WorldSector],
+    settings: &MapGenSettings,
+    biomes: &BiomeRegistry,
+) {
+    let is_water: Vec<bool> = sectors
+        .iter()
+        .map(|sector| biomes.is_water(sector.biome))
+        .collect();
+    let on_edge: Vec<bool> = sectors
+        .iter()
+        .map(|sector| touches_edge(&sector.border, settings.size))
+        .collect();
+    let neighbors: Vec<&[usize]> = sectors
+        .iter()
+        .map(|sector| sector.neighbors.as_slice())
+        .collect();
+
+    let (water_bodies, coastal) = label_water(
+        &is_water,
+        &on_edge,
+        &neighbors,
+        settings.inland_sea_min_size as usize,
+    );
+
+    for ((sector, water_body), coastal) in sectors.iter_mut().zip(water_bodies).zip(coastal) {
+        sector.water_body = water_body;
+        sector.coastal = coastal;
+    }
+}
+
+/// Flood fill connected water sectors and label each component, returning every sector's
+/// body of water and whether it's coastal land.
+/// This works on plain lists so saves from before water bodies can be labelled the same way.
+pub fn label_water(
+    is_water: &[bool],
+    on_edge: &[bool],
+    neighbors: &[&[usize]],
+    inland_sea_min_size: usize,
+) -> (Vec<Option<WaterBody>>, Vec<bool>) {
+    let mut water_bodies: Vec<Option<WaterBody>> = vec![None; is_water.len()];
+    let mut visited = vec![false; is_water.len()];
+
+    for start in 0..is_water.len() {
+        if !is_water[start] || visited[start] {
+            continue;
+        }
+
+        // Collect the whole component before deciding what it is
+        let mut component = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        visited[start] = true;
+        while let Some(i) = queue.pop_front() {
+            component.push(i);
+            for neighbor in neighbors[i] {
+                if is_water[*neighbor] && !visited[*neighbor] {
+                    visited[*neighbor] = true;
+                    queue.push_back(*neighbor);
+                }
+            }
+        }
+
+        let water_body = if component.iter().any(|i| on_edge[*i]) {
+            WaterBody::Ocean
+        } else if component.len() >= inland_sea_min_size {
+            WaterBody::InlandSea
+        } else {
+            WaterBody::Lake
+        };
+        for i in component {
+            water_bodies[i] = Some(water_body);
+        }
+    }
+
+    let coastal = (0..is_water.len())
+        .map(|i| !is_water[i] && neighbors[i].iter().any(|neighbor| is_water[*neighbor]))
+        .collect();
+
+    (water_bodies, coastal)
+}
+
+/// Whether a sector's border lies along the edge of a map of this size.
+pub fn touches_edge(border: &[Vec2], size: Vec2) -> bool {
+    const EPSILON: f32 = 0.01;
+    border.iter().any(|vertex| {
+        vertex.x <= EPSILON
+            || vertex.y <= EPSILON
+            || vertex.x >= size.x - EPSILON
+            || vertex.y >= size.y - EPSILON
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row of sectors, each joined to the ones beside it, from a pattern of `W` for water
+    /// and `L` for land. Only the first sector is on the edge of the map.
+    fn row(pattern: &str) -> (Vec<bool>, Vec<bool>, Vec<Vec<usize>>) {
+        let len = pattern.len();
+        let is_water = pattern.chars().map(|c| c == 'W').collect();
+        let on_edge = (0..len).map(|i| i == 0).collect();
+        let neighbors = (0..len)
+            .map(|i| {
+                [i.checked_sub(1), Some(i + 1).filter(|j| *j < len)]
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            })
+            .collect();
+        (is_water, on_edge, neighbors)
+    }
+
+    fn label(pattern: &str, inland_sea_min_size: usize) -> (Vec<Option<WaterBody>>, Vec<bool>) {
+        let (is_water, on_edge, neighbors) = row(pattern);
+        let neighbors: Vec<&[usize]> = neighbors.iter().map(Vec::as_slice).collect();
+        label_water(&is_water, &on_edge, &neighbors, inland_sea_min_size)
+    }
+
+    #[test]
+    fn water_is_labelled_by_size_and_edge() {
+        use WaterBody::*;
+
+        let (bodies, coastal) = label("WWLWWWLWWLLL", 3);
+        assert_eq!(
+            bodies,
+            [
+                Some(Ocean),
+                Some(Ocean),
+                None,
+                Some(InlandSea),
+                Some(InlandSea),
+                Some(InlandSea),
+                None,
+                Some(Lake),
+                Some(Lake),
+                None,
+                None,
+                None,
+            ]
+        );
+        let coastal_land: Vec<usize> = (0..coastal.len()).filter(|i| coastal[*i]).collect();
+        assert_eq!(coastal_land, [2, 6, 9]);
+    }
+
+    #[test]
+    fn inland_seas_need_the_minimum_size() {
+        let (bodies, _) = label("LWWWL", 3);
+        assert_eq!(bodies[1..4], [Some(WaterBody::InlandSea); 3]);
+
+        let (bodies, _) = label("LWWWL", 4);
+        assert_eq!(bodies[1..4], [Some(WaterBody::Lake); 3]);
+    }
+
+    #[test]
+    fn any_edge_sector_makes_an_ocean() {
+        // However large the rest of the water is, touching the edge once is enough
+        let (bodies, _) = label("WWWWWWWWWL", 3);
+        assert!(
+            bodies[..9]
+                .iter()
+                .all(|body| *body == Some(WaterBody::Ocean))
+        );
+    }
+
+    #[test]
+    fn borders_on_the_map_edge_are_detected() {
+        let size = vec2(100.0, 50.0);
+        let square = |min: Vec2, max: Vec2| vec![min, vec2(max.x, min.y), max, vec2(min.x, max.y)];
+        assert!(touches_edge(
+            &square(vec2(0.0, 10.0), vec2(5.0, 15.0)),
+            size
+        ));
+        assert!(touches_edge(
+            &square(vec2(90.0, 10.0), vec2(100.0, 15.0)),
+            size
+        ));
+        assert!(touches_edge(
+            &square(vec2(40.0, 45.0), vec2(45.0, 50.0)),
+            size
+        ));
+        assert!(!touches_edge(
+            &square(vec2(40.0, 10.0), vec2(45.0, 15.0)),
+            size
+        ));
+    }
+}
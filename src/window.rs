@@ -180,6 +180,18 @@ impl EntryBuilder {
         }
     }
 
+    /// A small square of color followed by a label, as in a map legend.
+    pub fn swatch(color: Color, text: &str) -> EntryBuilder {
+        EntryBuilder {
+            entry_type: EntryType::Swatch {
+                color,
+                text: text.to_string(),
+            },
+            centered: false,
+            subwindow: None,
+        }
+    }
+
     pub fn centered(mut self) -> EntryBuilder {
         self.centered = true;
         self
@@ -204,6 +216,26 @@ impl EntryBuilder {
             EntryType::Button { text } => commands
                 .spawn((Button, new_node, layout, children![Text::new(text)]))
                 .id(),
+            EntryType::Swatch { color, text } => commands
+                .spawn((
+                    Node {
+                        align_items: AlignItems::Center,
+                        ..new_node
+                    },
+                    children![
+                        (
+                            Node {
+                                width: Val::Px(14.0),
+                                height: Val::Px(14.0),
+                                margin: UiRect::right(Val::Px(5.0)),
+                                ..Node::default()
+                            },
+                            BackgroundColor(*color),
+                        ),
+                        (Text::new(text), layout),
+                    ],
+                ))
+                .id(),
         };
 
         if let Some(window) = &self.subwindow {
@@ -227,6 +259,9 @@ enum EntryType {
 
     /// Displays a button with a text label
     Button { text: String },
+
+    /// Displays a square of color next to a text label
+    Swatch { color: Color, text: String },
 }
 
 /// Component for a UI button that toggles another window's visibility
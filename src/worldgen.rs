@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_prng::WyRand;
+use rand::SeedableRng;
+
+use crate::{
+    biome::{self, Biome, BiomeRegistry},
+    city, erosion,
+    map::{self, Map, Sector},
+    map_image::MapImages,
+    province::{self, Province, WorldProvince},
+    resource::{self, Resource, ResourceNode, ResourceNodeType},
+    river,
+    road::{self, Road},
+    settings::{GameplaySettings, MapGenSettings},
+    shipment::Shipment,
+    water::{self, WaterBody},
+};
+
+/// A whole generated world as plain data, independent of the ECS.
+/// References between parts of the world are indices into the lists in this struct.
+pub struct GeneratedWorld {
+    pub seed: u64,
+    pub sectors: Vec<WorldSector>,
+    pub cities: Vec<WorldCity>,
+    pub nodes: Vec<WorldNode>,
+    pub roads: Vec<WorldRoad>,
+    pub provinces: Vec<WorldProvince>,
+}
+
+/// A single polygon in the voronoi diagram, see [`Sector`].
+#[derive(Debug, Clone)]
+pub struct WorldSector {
+    pub site: Vec2,
+    pub border: Vec<Vec2>,
+    pub centroid: Vec2,
+    pub height: f32,
+    pub biome: Option<Biome>,
+    pub water_body: Option<WaterBody>,
+    pub coastal: bool,
+    pub cost: f32,
+    pub river_flow: f32,
+    pub downstream: Option<usize>,
+    pub neighbors: Vec<usize>,
+}
+
+/// A generated city, see [`City`](crate::city::City).
+#[derive(Debug, Clone)]
+pub struct WorldCity {
+    pub name: String,
+    pub sector: usize,
+    pub position: Vec2,
+    pub population: u32,
+    pub resource_nodes: Vec<usize>,
+}
+
+/// A generated resource node, see [`ResourceNode`].
+#[derive(Debug, Clone)]
+pub struct WorldNode {
+    pub node_type: ResourceNodeType,
+    pub produces: Resource,
+    pub sector: usize,
+    pub city: usize,
+    pub position: Vec2,
+
+    /// The road to this node's city, or `None` until roads have been generated.
+    pub road: Option<usize>,
+
+    /// How far through its first wagon timer this node starts, from 0 to 1.
+    pub timer_offset: f32,
+}
+
+/// A generated road, see [`Road`].
+#[derive(Clone)]
+pub struct WorldRoad {
+    pub start_sector: usize,
+    pub end_sector: usize,
+    pub path: Vec<usize>,
+    pub curve: CubicCurve<Vec2>,
+    pub length: f32,
+}
+
+/// Generate a whole world from a seed without touching the ECS.
+/// City names are drawn from `names` without repeats.
+pub fn generate_world(
+    settings: &MapGenSettings,
+    biomes: &BiomeRegistry,
+    images: &MapImages,
+    seed: u64,
+    names: &[String],
+) -> GeneratedWorld {
+    let mut rng = WyRand::seed_from_u64(seed);
+
+    let mut sectors = map::generate_sectors(settings, images, &mut rng);
+    erosion::erode(&mut sectors, settings);
+    biome::generate_biomes(&mut sectors, settings, biomes, images, &mut rng);
+    water::classify_water(&mut sectors, settings, biomes);
+    erosion::add_slope_cost(&mut sectors, settings);
+    river::generate_rivers(&mut sectors, settings, biomes, &mut rng);
+    let mut cities = city::place_cities(&sectors, names, settings, biomes, &mut rng);
+    let mut nodes =
+        resource::place_resource_nodes(&sectors, &mut cities, settings, biomes, &mut rng);
+    let roads = road::plan_node_roads(&sectors, &cities, &mut nodes);
+    let provinces =
+        province::generate_provinces(&sectors, &cities, names, settings, biomes, &mut rng);
+
+    GeneratedWorld {
+        seed,
+        sectors,
+        cities,
+        nodes,
+        roads,
+        provinces,
+    }
+}
+
+/// Spawn a generated world into the ECS, filling in the map's list of sectors.
+pub fn spawn_world(
+    commands: &mut Commands,
+    e_map: Entity,
+    map: &mut Map,
+    world: &GeneratedWorld,
+    gameplay_settings: &GameplaySettings,
+) {
+    let mut reserve =
+        |len: usize| -> Vec<Entity> { (0..len).map(|_| commands.spawn_empty().id()).collect() };
+    let e_sectors = reserve(world.sectors.len());
+    let e_nodes = reserve(world.nodes.len());
+    let e_roads = reserve(world.roads.len());
+
+    map.sectors = e_sectors.clone();
+    commands.entity(e_map).add_children(&e_sectors);
+
+    for (sector, e_sector) in world.sectors.iter().zip(&e_sectors) {
+        commands.entity(*e_sector).insert(Sector {
+            site: sector.site,
+            border: sector.border.clone(),
+            centroid: sector.centroid,
+            height: sector.height,
+            biome: sector.biome,
+            water_body: sector.water_body,
+            coastal: sector.coastal,
+            cost: sector.cost,
+            river_flow: sector.river_flow,
+            downstream: sector.downstream.map(|i| e_sectors[i]),
+            neighbors: sector.neighbors.iter().map(|i| e_sectors[*i]).collect(),
+        });
+    }
+
+    let e_cities: Vec<Entity> = world
+        .cities
+        .iter()
+        .map(|city| {
+            city::spawn_city(
+                commands,
+                city.name.clone(),
+                e_sectors[city.sector],
+                city.resource_nodes.iter().map(|i| e_nodes[*i]).collect(),
+                city.position,
+                city.population,
+            )
+        })
+        .collect();
+
+    let e_provinces: Vec<Entity> = world
+        .provinces
+        .iter()
+        .map(|province| {
+            commands
+                .spawn(Province {
+                    name: province.name.clone(),
+                    capital: province.capital.map(|i| e_cities[i]),
+                    sectors: province.sectors.iter().map(|i| e_sectors[*i]).collect(),
+                })
+                .id()
+        })
+        .collect();
+    commands.entity(e_map).add_children(&e_provinces);
+
+    for (node, e_node) in world.nodes.iter().zip(&e_nodes) {
+        let spawn_time = gameplay_settings.node_wagon_spawn_time;
+        let wagon_timer = Timer::from_seconds(spawn_time, TimerMode::Repeating)
+            .tick(Duration::from_secs_f32(node.timer_offset * spawn_time))
+            .clone();
+
+        // Nodes are children of their city, so their transform is relative to it
+        let city_pos = world.cities[node.city].position;
+        commands.entity(*e_node).insert((
+            ResourceNode {
+                node_type: node.node_type,
+                produces: Shipment {
+                    resource: node.produces,
+                    quantity: 1,
+                },
+                sector: e_sectors[node.sector],
+                wagon_timer,
+                city: e_cities[node.city],
+                road: node.road.map_or(Entity::PLACEHOLDER, |i| e_roads[i]),
+            },
+            Transform::from_translation((node.position - city_pos).extend(0.0)),
+        ));
+        commands.entity(e_cities[node.city]).add_child(*e_node);
+    }
+
+    for (road, e_road) in world.roads.iter().zip(&e_roads) {
+        commands.entity(*e_road).insert((
+            Road {
+                start_sector: e_sectors[road.start_sector],
+                end_sector: e_sectors[road.end_sector],
+                path: road.path.iter().map(|i| e_sectors[*i]).collect(),
+                curve: road.curve.clone(),
+                length: road.length,
+                speed_multiplier: 1.0,
+            },
+            Visibility::Visible,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::city_names;
+
+    #[test]
+    fn same_seed_generates_same_world() {
+        let settings = MapGenSettings::default();
+        let biomes = BiomeRegistry::load(Path::new("assets/biomes.ron")).unwrap();
+        let names = city_names::read_name_file(Path::new("assets/city-names.csv")).unwrap();
+        let images = MapImages::default();
+
+        let first = generate_world(&settings, &biomes, &images, 7, &names);
+        let second = generate_world(&settings, &biomes, &images, 7, &names);
+        assert_eq!(first.fingerprint(), second.fingerprint());
+
+        let other = generate_world(&settings, &biomes, &images, 8, &names);
+        assert_ne!(first.fingerprint(), other.fingerprint());
+    }
+}